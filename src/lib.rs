@@ -0,0 +1,34 @@
+//! Templates for character sheets and other rule-heavy documents
+//! 
+//! A [`Template`] is a tree of named nodes. Leaves hold values or expressions referring to other nodes, groups hold
+//! other nodes, and metanodes attach extra behavior like sums, modifiers and defaults to their parents. Values are
+//! evaluated on demand and cached until something they depend on changes.
+//! 
+//! ```
+//! use peanut::{NodeTree, Template, Value};
+//! 
+//! let mut template = Template::load("leaf strength = 16\nleaf modifier = (strength - 10) / 2\n").unwrap();
+//! let id = template.get_leaf("modifier").unwrap().id;
+//! 
+//! assert_eq!(template.eval_leaf(id), Ok(Value::Integer(3)));
+//! ```
+
+mod template;
+
+pub use template::{
+    // The template and handles for editing its nodes
+    Template, Handle, NodeTree, NodeHandle, LeafHandle, GroupHandle, MetaHandle, NodeId, Integer,
+    // Nodes
+    Node, Leaf, Group, Meta, Metadata, MetadataStart, Constraint, Contribution, ConcatElement, IntFormat, IdentMode,
+    Modifier, ModifierOp, Table, TableKey, Collection,
+    // Values and expressions
+    Value, ValueKind, Expr, InfixOp, OpKind, Aggregate, AggregateKind, Lookup, RefForm,
+    // Evaluation
+    EvalCache, Explanation, Step, TraceEvent, TraceHook, Walk, WalkOrder, TreeView,
+    // Errors
+    AddNodeError, RemoveNodeError, EditLeafError, EditMetaError, PushCommonError, EvalError, ErrorReport, ParseError,
+    ParseErrorKind, LoadError, LoadErrorKind, RebaseError, InstanceError, CollectionError,
+    TransactionError, MergeError,
+    // Inheritance and merging
+    Override, Change, Conflict,
+};
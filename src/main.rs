@@ -1,104 +1,107 @@
-mod template;
+mod repl;
+
+use std::{env, fs, process::ExitCode};
+
+use peanut::{Expr, LeafHandle, Node, NodeId, NodeTree, Template};
+
+const USAGE: &str = "usage:
+  peanut eval <file> <path>            evaluate a leaf
+  peanut set <file> <path> <value>     set a leaf to an expression and save the file
+  peanut tree [--values] <file>        print the template as a tree
+  peanut check <file>                  report cycles, type errors and broken constraints
+  peanut explain <file> <path>         show how a leaf's value is worked out
+  peanut diff <old> <new>              list what changed between two templates
+  peanut merge <file> <base> <theirs>  apply the changes from base to theirs to a file based on base
+  peanut repl [file]                   explore and edit a template interactively";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn run(args: &[&str]) -> Result<(), String> {
+    match args {
+        ["eval", file, path] => {
+            let mut template = load(file)?;
+            let id = find_leaf(&template, path)?;
+            let value = template.eval_leaf_report(id).map_err(|err| err.to_string())?;
+
+            println!("{value}");
+        },
+        ["set", file, path, value] => {
+            let mut template = load(file)?;
+            let id = find_leaf(&template, path)?;
+            let expr = template.parse_expr(value).map_err(|err| format!("{value}: {err}"))?;
+
+            let mut leaf = LeafHandle { id, template: &mut template };
+            match expr {
+                Expr::Literal(value) => leaf.set_value(value),
+                expr => leaf.set_expr(expr),
+            }.map_err(|err| format!("{path}: {err}"))?;
+
+            fs::write(file, template.save()).map_err(|err| format!("{file}: {err}"))?;
+        },
+        ["tree", file] => print!("{}", load(file)?.tree()),
+        ["tree", "--values", file] => print!("{}", load(file)?.tree().with_values(true)),
+        ["check", file] => {
+            let reports = load(file)?.check();
+
+            for report in &reports {
+                println!("{report}");
+            }
+
+            if !reports.is_empty() {
+                return Err(format!("{file}: found {} problem(s)", reports.len()));
+            }
+        },
+        ["explain", file, path] => {
+            let template = load(file)?;
+            let id = find_leaf(&template, path)?;
+            let explanation = template.explain(id).map_err(|err| template.report_eval(err, &[id]).to_string())?;
+
+            print!("{explanation}");
+        },
+        ["diff", old, new] => {
+            for change in load(old)?.diff(&load(new)?) {
+                println!("{change}");
+            }
+        },
+        ["merge", file, base, theirs] => {
+            let mut template = load(file)?;
+            template.merge(&load(base)?, &load(theirs)?).map_err(|err| format!("{file}: {err}"))?;
+
+            fs::write(file, template.save()).map_err(|err| format!("{file}: {err}"))?;
+        },
+        ["repl"] => repl::Repl::new(Template::new(), None).run_terminal().map_err(|err| err.to_string())?,
+        ["repl", file] => {
+            let template = load(file)?;
+
+            repl::Repl::new(template, Some(file.to_string())).run_terminal().map_err(|err| err.to_string())?;
+        },
+        _ => return Err(USAGE.to_owned()),
+    }
 
-use template::{Expr, InfixOp, OpKind, Value};
-pub use template::{Template, AddNodeError, NodeTree};
+    Ok(())
+}
 
-use crate::template::{Handle, MetadataStart, MetaHandle, NodeId, LeafHandle};
+fn load(file: &str) -> Result<Template, String> {
+    let source = fs::read_to_string(file).map_err(|err| format!("{file}: {err}"))?;
 
-fn main() {
-    let ability_names = ["strength", "dexterity", "constitution", "intelligence", "wisdom", "charisma"];
-    let mut template = Template::new();
+    Template::load(&source).map_err(|err| format!("{file}: {err}"))
+}
 
-    let mut ability_scores = template.add_group("ability_scores").unwrap();
-    for name in ability_names.iter() {
-        ability_scores.add_leaf(name, false).unwrap();
+fn find_leaf(template: &Template, path: &str) -> Result<NodeId, String> {
+    match template.get_node(path) {
+        Some(Node::Leaf(leaf)) => Ok(leaf.id),
+        Some(_) => Err(format!("{path}: not a leaf")),
+        None => Err(format!("{path}: no node found")),
     }
-
-    let mut abilities = template.add_group("abilities").unwrap();
-    for name in ability_names.iter() {
-        abilities.add_leaf(name, false).unwrap();
-    }    
-
-    // for name in ability_names.iter() {
-    {
-        let mut node = template.get_group_handle("abilities").unwrap();
-        let mut meta = node.add_meta("mod", MetadataStart::Common).unwrap();
-        let meta_id = meta.id;
-
-        let name_id = meta.add_meta("name", MetadataStart::Ident).unwrap().id;
-        let mut concat_node = meta.add_meta("source", MetadataStart::Concat).unwrap();
-        concat_node.set_value(template::Metadata::Concat(vec![Expr::Literal(Value::String("ability_scores.".to_owned())), Expr::Reference(name_id)])).unwrap();
-        let concat_id = concat_node.id;
-
-        let mut meta = MetaHandle { id: meta_id, template: &mut template };
-        let mut modifier = meta.add_leaf("mod", false).unwrap();
-        modifier.set_expr(Expr::InfixOp(
-            Box::new(
-                InfixOp { 
-                    lhs: Expr::InfixOp(
-                        Box::new(
-                            InfixOp { 
-                                lhs: Expr::IdentRef(concat_id), 
-                                rhs: Expr::Literal(Value::Integer(10)), 
-                                kind: OpKind::Sub 
-                            }
-                        )
-                    ), 
-                    rhs: Expr::Literal(Value::Integer(2)),
-                    kind: OpKind::Div,
-                }
-            ))
-        ).unwrap();
-    }
-
-    // for name in ability_names.iter() {
-    //     let value_id = template.get_leaf("abilities.mod.mod").unwrap().id;
-    //     let mut node = template.get_leaf_handle(&format!("abilities.{name}")).unwrap();
-    //     node.set_expr(Expr::Reference(value_id)).unwrap();
-    // }
-
-    let scores = [20, 16, 18, 10, 8, 12];
-
-    ability_names.iter().zip(scores.iter()).for_each(|(name, score)| {
-        let mut handle = template.get_leaf_handle(&format!("ability_scores.{name}")).unwrap();
-        handle.set_value(Value::Integer(*score)).unwrap();
-    });
-
-    println!("{template:#?}");
-    println!("Evaluating modifiers");
-    let modifiers: Vec<Value> = ability_names.iter().map(|name| {
-        let id = template.get_leaf(&format!("abilities.{name}")).unwrap().id;
-
-        println!("abilities.{name}.id: {id}");
-
-        template.eval_leaf(id).unwrap()
-    }).collect();
-
-    let mut sum = template.add_leaf("mod_sum", false).unwrap();
-    let sum_id = sum.id;
-    let mut sum_meta = sum.add_meta("name", MetadataStart::Sum).unwrap();
-    let sum_meta_id = sum_meta.id;
-
-    println!("Adding modifiers to sum");
-    sum_meta.set_value(template::Metadata::Sum(modifiers.iter().map(|value| if let Value::Integer(value) = value {
-        *value
-    } else {
-        unreachable!();
-    }).collect())).unwrap();
-
-    let mut sum = LeafHandle { id: sum_id, template: &mut template };
-    sum.set_expr(Expr::Reference(sum_meta_id)).unwrap();
-    
-    let sum = template.eval_leaf(sum_id).unwrap();
-
-    println!("STR: {:?}\nDEX: {:?}\nCON: {:?}\nINT: {:?}\nWIS: {:?}\nCHA: {:?}\nTotal: {:?}",
-        modifiers[0],
-        modifiers[1],
-        modifiers[2],
-        modifiers[3],
-        modifiers[4],
-        modifiers[5],
-        sum,
-    );
-    assert_eq!(modifiers[5], Value::Integer(1));
-}
\ No newline at end of file
+}
@@ -0,0 +1,513 @@
+use std::{collections::HashMap, fs, io::{self, BufRead, IsTerminal, Write}, process::{Command, Stdio}};
+
+use peanut::{EvalCache, Expr, LeafHandle, Node, NodeId, Template, Value};
+
+const HELP: &str = "commands:
+  cd [path]              move to a node, `..` goes up and `/` or nothing goes to the root
+  ls [path]              list the children of a node
+  pwd                    print the current path
+  eval <path>            evaluate a leaf
+  explain <path>         show how a leaf's value is worked out
+  set <path> <expr>      set a leaf and show every value that changed
+  leaf <name> [= expr]   add a leaf here
+  group <name>           add a group here
+  tree                   print everything below here with values
+  check                  report cycles, type errors and broken constraints
+  undo                   undo the last edit
+  redo                   redo the last edit that was undone
+  save [file]            save the template
+  complete <partial>     list the paths starting with `partial`
+  history                list previous commands, `!n` runs command n again
+  quit                   leave
+
+paths are relative to the current node, or to the root if they start with `/`
+paths in expressions are relative to the current node, or to the root if there's nothing there
+press Tab to complete the path being typed, or list the paths it could be, and up and down for previous commands";
+
+/// An interactive session editing a single template
+pub struct Repl {
+    template: Template,
+    /// Where the template was loaded from, and where it's saved by default
+    file: Option<String>,
+    /// The node paths are relative to
+    current: NodeId,
+    history: Vec<String>,
+}
+
+impl Repl {
+    pub fn new(template: Template, file: Option<String>) -> Self {
+        Repl { template, file, current: 0, history: Vec::new() }
+    }
+
+    /// Runs the session on the terminal until input ends or `quit` is entered
+    ///
+    /// If the terminal can be switched to reading a key at a time with `stty`, Tab completes the path being typed.
+    /// Otherwise, like when commands are piped in, whole lines are read by [`Repl::run`]
+    pub fn run_terminal(&mut self) -> io::Result<()> {
+        let raw = match io::stdin().is_terminal() {
+            true => RawMode::enable(),
+            false => None,
+        };
+
+        match raw {
+            Some(_raw) => self.run_keys(io::stdin().lock(), io::stdout()),
+            None => self.run(io::stdin().lock(), io::stdout()),
+        }
+    }
+
+    /// Reads commands from `input` until it ends or `quit` is entered
+    ///
+    /// Commands are read a whole line at a time, so the terminal's own line editing is used and Tab doesn't complete
+    /// anything. Use `complete` to list the paths a partial path could be completed to
+    pub fn run(&mut self, input: impl BufRead, output: impl Write) -> io::Result<()> {
+        let mut lines = input.lines();
+
+        self.run_with(|_, _| lines.next().transpose(), output)
+    }
+
+    /// Reads commands from `input` a key at a time until it ends, `quit` is entered or Ctrl-D is pressed
+    ///
+    /// Keys are echoed to `output` as they're typed, so `input` should be a terminal that doesn't echo them itself.
+    /// Tab completes the path being typed, or lists every path it could be completed to, and up and down go through
+    /// previous commands
+    pub fn run_keys(&mut self, input: impl BufRead, output: impl Write) -> io::Result<()> {
+        let mut keys = input.bytes();
+
+        self.run_with(|repl, output| repl.edit_line(&mut keys, output), output)
+    }
+
+    fn run_with<W: Write>(&mut self, mut next_line: impl FnMut(&Self, &mut W) -> io::Result<Option<String>>, mut output: W) -> io::Result<()> {
+        loop {
+            write!(output, "{}", self.prompt())?;
+            output.flush()?;
+
+            let Some(line) = next_line(self, &mut output)? else {
+                return writeln!(output);
+            };
+            let line = line.trim();
+
+            // Rerunning a command puts it in the history again, rather than the `!n`
+            let line = match line.strip_prefix('!').map(str::parse::<usize>) {
+                Some(Ok(number)) => match number.checked_sub(1).and_then(|index| self.history.get(index)) {
+                    Some(line) => line.clone(),
+                    None => {
+                        writeln!(output, "no command {number} in history, they're numbered from 1")?;
+                        continue;
+                    },
+                },
+                _ => line.to_owned(),
+            };
+
+            if line.is_empty() {
+                continue;
+            }
+            if line == "quit" || line == "exit" {
+                return Ok(());
+            }
+
+            self.history.push(line.clone());
+            match self.execute(&line) {
+                Ok(out) if out.is_empty() => (),
+                Ok(out) => writeln!(output, "{}", out.trim_end())?,
+                Err(err) => writeln!(output, "error: {err}")?,
+            }
+        }
+    }
+
+    /// Runs a single command, returning what it printed
+    pub fn execute(&mut self, line: &str) -> Result<String, String> {
+        let (command, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match command {
+            "help" => Ok(HELP.to_owned()),
+            "pwd" => Ok(format!("/{}", self.template.path_of(self.current).unwrap_or_default())),
+            "cd" => {
+                self.current = match rest {
+                    "" => 0,
+                    path => self.find(path)?,
+                };
+
+                Ok(String::new())
+            },
+            "ls" => {
+                let id = self.find(rest)?;
+                let children: Vec<_> = self.template.children(id).into_iter().map(|(name, child)| {
+                    if self.template.get_group_by_id(child).is_some() {
+                        format!("{name}/")
+                    } else if self.template.get_meta_by_id(child).is_some() {
+                        format!("{name} (meta)")
+                    } else {
+                        name.to_owned()
+                    }
+                }).collect();
+
+                Ok(children.join("\n"))
+            },
+            "eval" => {
+                let id = self.find(rest)?;
+
+                self.template.eval_leaf_report(id).map(|value| value.to_string()).map_err(|err| err.to_string())
+            },
+            "explain" => {
+                let id = self.find(rest)?;
+
+                self.template.explain(id).map(|explanation| explanation.to_string())
+                    .map_err(|err| self.template.report_eval(err, &[id]).to_string())
+            },
+            "set" => {
+                let (path, expr) = rest.split_once(char::is_whitespace).ok_or("usage: set <path> <expr>")?;
+                let id = self.find(path)?;
+                let expr = self.template.parse_expr_from(expr.trim(), self.current).map_err(|err| err.to_string())?;
+
+                self.set(id, expr)
+            },
+            "leaf" => {
+                let (name, expr) = match rest.split_once('=') {
+                    Some((name, expr)) => (name.trim(), Some(expr.trim())),
+                    None => (rest, None),
+                };
+                let expr = expr.map(|expr| self.template.parse_expr_from(expr, self.current)).transpose().map_err(|err| err.to_string())?;
+                let before = self.leaf_values();
+                let current = self.current;
+
+                // Adding the leaf and setting it are undone together
+                self.template.transaction(|template| {
+                    let id = template.add_leaf_to(name, current, false)
+                        .map(|leaf| leaf.id)
+                        .map_err(|err| template.report_add(err, current, name).to_string())?;
+                    let mut leaf = LeafHandle { id, template };
+
+                    match expr {
+                        Some(Expr::Literal(value)) => leaf.set_value(value).map(|_| ()),
+                        Some(expr) => leaf.set_expr(expr).map(|_| ()),
+                        None => Ok(()),
+                    }.map_err(|err| err.to_string())
+                }).map_err(|err| err.to_string())?;
+
+                Ok(self.changed_since(&before))
+            },
+            "group" => self.template.add_group_to(rest, self.current)
+                .map(|_| String::new())
+                .map_err(|err| self.template.report_add(err, self.current, rest).to_string()),
+            "tree" => Ok(self.template.tree_from(self.current).with_values(true).to_string()),
+            "check" => {
+                let reports: Vec<_> = self.template.check().iter().map(ToString::to_string).collect();
+
+                match reports.is_empty() {
+                    true => Ok("no problems found".to_owned()),
+                    false => Ok(reports.join("\n")),
+                }
+            },
+            "undo" => match self.template.undo() {
+                true => Ok(String::new()),
+                false => Err("nothing to undo".to_owned()),
+            },
+            "redo" => match self.template.redo() {
+                true => Ok(String::new()),
+                false => Err("nothing to redo".to_owned()),
+            },
+            "save" => {
+                let file = match rest {
+                    "" => self.file.clone().ok_or("no file to save to, use `save <file>`")?,
+                    file => file.to_owned(),
+                };
+
+                fs::write(&file, self.template.save()).map_err(|err| format!("{file}: {err}"))?;
+                self.file = Some(file);
+
+                Ok(String::new())
+            },
+            "complete" => Ok(self.complete(rest).join("\n")),
+            "history" => {
+                let lines: Vec<_> = self.history.iter().enumerate().map(|(index, line)| format!("{:>4}  {line}", index + 1)).collect();
+
+                Ok(lines.join("\n"))
+            },
+            _ => Err(format!("unknown command `{command}`, try `help`")),
+        }
+    }
+
+    fn prompt(&self) -> String {
+        format!("peanut:/{}> ", self.template.path_of(self.current).unwrap_or_default())
+    }
+
+    /// Reads a line a key at a time, echoing it to `output`, or `None` if input ends or Ctrl-D is pressed on an empty line
+    ///
+    /// Up and down go through the history, keeping whatever was being typed to come back to past the newest command
+    fn edit_line(&self, keys: &mut impl Iterator<Item = io::Result<u8>>, output: &mut impl Write) -> io::Result<Option<String>> {
+        let mut line = Vec::new();
+        // The history entry being shown, where the end of the history is the line being typed
+        let mut recalled = self.history.len();
+        let mut typing = Vec::new();
+
+        while let Some(key) = keys.next().transpose()? {
+            match key {
+                b'\r' | b'\n' => {
+                    writeln!(output)?;
+
+                    return Ok(Some(String::from_utf8_lossy(&line).into_owned()));
+                },
+                // Ctrl-D
+                4 if line.is_empty() => return Ok(None),
+                // Ctrl-C abandons the line
+                3 => {
+                    writeln!(output, "^C")?;
+
+                    return Ok(Some(String::new()));
+                },
+                // Backspace and delete remove a whole character, however many bytes it is
+                8 | 127 => {
+                    if let Some(start) = line.iter().rposition(|byte| byte & 0xc0 != 0x80) {
+                        line.truncate(start);
+                        write!(output, "\x08 \x08")?;
+                    }
+                },
+                b'\t' => {
+                    let typed = String::from_utf8_lossy(&line).into_owned();
+                    let partial = typed.rsplit(char::is_whitespace).next().unwrap_or_default();
+                    let options = self.complete(partial);
+                    let common = common_prefix(&options);
+
+                    if common.len() > partial.len() {
+                        write!(output, "{}", &common[partial.len()..])?;
+                        line.extend_from_slice(&common.as_bytes()[partial.len()..]);
+                    } else if options.len() > 1 {
+                        write!(output, "\n{}\n{}{typed}", options.join("  "), self.prompt())?;
+                    }
+                },
+                // Other escape sequences, like left and right, aren't supported so the whole sequence is skipped
+                0x1b => {
+                    let next = match escape_sequence(keys)? {
+                        Some(b'A') if recalled > 0 => recalled - 1,
+                        Some(b'B') if recalled < self.history.len() => recalled + 1,
+                        _ => continue,
+                    };
+
+                    if recalled == self.history.len() {
+                        typing = line;
+                    }
+                    recalled = next;
+                    line = match self.history.get(recalled) {
+                        Some(command) => command.as_bytes().to_vec(),
+                        None => typing.clone(),
+                    };
+
+                    // Go back to the start of the line to write over it, then clear anything left from a longer line
+                    write!(output, "\r{}{}\x1b[K", self.prompt(), String::from_utf8_lossy(&line))?;
+                },
+                key if key < 0x20 => (),
+                key => {
+                    output.write_all(&[key])?;
+                    line.push(key);
+                },
+            }
+
+            output.flush()?;
+        }
+
+        Ok(None)
+    }
+
+    /// Gets every path that `partial` could be completed to, relative to the current node like `partial` is
+    pub fn complete(&self, partial: &str) -> Vec<String> {
+        let (parent, prefix, start) = match partial.rsplit_once('.') {
+            Some((parent, start)) => (self.find(parent).ok(), format!("{parent}."), start),
+            None if partial.starts_with('/') => (Some(0), "/".to_owned(), &partial[1..]),
+            None => (Some(self.current), String::new(), partial),
+        };
+
+        let Some(parent) = parent else {
+            return Vec::new();
+        };
+
+        let mut out: Vec<_> = self.template.children(parent).into_iter()
+            .filter(|(name, _)| name.starts_with(start))
+            .map(|(name, _)| format!("{prefix}{name}"))
+            .collect();
+        out.sort();
+
+        out
+    }
+
+    /// Finds the node at a path relative to the current node
+    fn find(&self, path: &str) -> Result<NodeId, String> {
+        let found = match path {
+            "" => Some(self.current),
+            "/" => Some(0),
+            ".." => self.template.ancestors(self.current).first().copied().or(Some(0)),
+            path => match path.strip_prefix('/') {
+                Some(path) => self.template.get_node_from(path, 0),
+                None => self.template.get_node_from(path, self.current),
+            },
+        };
+
+        found.ok_or_else(|| format!("{path}: no node found"))
+    }
+
+    /// Sets a leaf, returning every leaf whose value changed because of it
+    fn set(&mut self, id: NodeId, expr: Expr) -> Result<String, String> {
+        let before = self.leaf_values();
+
+        let mut leaf = LeafHandle { id, template: &mut self.template };
+        match expr {
+            Expr::Literal(value) => leaf.set_value(value),
+            expr => leaf.set_expr(expr),
+        }.map_err(|err| err.to_string())?;
+
+        Ok(self.changed_since(&before))
+    }
+
+    /// Lists every leaf whose value is different from `before`
+    fn changed_since(&self, before: &HashMap<NodeId, Value>) -> String {
+        let after = self.leaf_values();
+        let mut changed: Vec<_> = after.iter()
+            .filter(|(id, value)| before.get(id) != Some(value))
+            .filter_map(|(id, value)| Some(format!("{} = {value}", self.template.path_of(*id)?)))
+            .collect();
+        changed.sort();
+
+        changed.join("\n")
+    }
+
+    /// Evaluates every leaf which has a value
+    fn leaf_values(&self) -> HashMap<NodeId, Value> {
+        let mut cache = EvalCache::new();
+
+        self.template.depth_first(0)
+            .filter_map(|(_, node)| match node {
+                Node::Leaf(leaf) => Some(leaf.id),
+                _ => None,
+            })
+            .filter_map(|id| Some((id, self.template.eval_leaf_cached(id, &mut cache).ok()?)))
+            .collect()
+    }
+}
+
+/// Reads the rest of an escape sequence after the escape, returning the key that ends it
+fn escape_sequence(keys: &mut impl Iterator<Item = io::Result<u8>>) -> io::Result<Option<u8>> {
+    if let Some(b'[' | b'O') = keys.next().transpose()? {
+        while let Some(key) = keys.next().transpose()? {
+            if (0x40..=0x7e).contains(&key) {
+                return Ok(Some(key));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// The longest start every option has in common
+fn common_prefix(options: &[String]) -> &str {
+    let Some(first) = options.first() else {
+        return "";
+    };
+
+    let mut len = first.len();
+    for option in &options[1..] {
+        len = first.bytes().zip(option.bytes()).take(len).take_while(|(a, b)| a == b).count();
+    }
+    while !first.is_char_boundary(len) {
+        len -= 1;
+    }
+
+    &first[..len]
+}
+
+/// Keeps the terminal giving keys as they're pressed without echoing them, until it's dropped
+struct RawMode {
+    /// The settings to put back, as printed by `stty -g`
+    saved: String,
+}
+
+impl RawMode {
+    /// Switches the terminal over with `stty`, or returns `None` if that isn't possible
+    fn enable() -> Option<RawMode> {
+        let saved = Command::new("stty").arg("-g").stdin(Stdio::inherit()).output().ok()?;
+        if !saved.status.success() {
+            return None;
+        }
+        let saved = String::from_utf8(saved.stdout).ok()?.trim().to_owned();
+
+        // Ctrl-C is handled as a key too, so the terminal is always put back
+        let status = Command::new("stty").args(["-icanon", "-echo", "-isig", "min", "1"]).stdin(Stdio::inherit()).status().ok()?;
+
+        status.success().then_some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = Command::new("stty").arg(&self.saved).stdin(Stdio::inherit()).status();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Repl;
+    use peanut::Template;
+
+    #[test]
+    fn repl_session() {
+        let template = Template::load("group abilities\nleaf abilities.strength = 16\nleaf abilities.mod = (abilities.strength - 10) / 2\n").unwrap();
+        let mut repl = Repl::new(template, None);
+
+        assert_eq!(repl.execute("cd abilities"), Ok(String::new()));
+        assert_eq!(repl.execute("pwd"), Ok("/abilities".to_owned()));
+        assert_eq!(repl.execute("ls"), Ok("strength\nmod".to_owned()));
+        assert_eq!(repl.complete("st"), ["strength"]);
+        assert_eq!(repl.complete("/abilities.m"), ["/abilities.mod"]);
+        assert_eq!(repl.execute("set strength 18"), Ok("abilities.mod = 4\nabilities.strength = 18".to_owned()));
+        assert_eq!(repl.execute("leaf double = abilities.mod * 2"), Ok("abilities.double = 8".to_owned()));
+        assert_eq!(repl.execute("cd .."), Ok(String::new()));
+        assert_eq!(repl.execute("eval abilities.double"), Ok("8".to_owned()));
+        assert_eq!(repl.execute("undo"), Ok(String::new()));
+        assert!(repl.execute("eval abilities.double").is_err());
+        assert_eq!(repl.execute("ls abilities"), Ok("strength\nmod".to_owned()));
+        assert_eq!(repl.execute("redo"), Ok(String::new()));
+        assert_eq!(repl.execute("redo"), Err("nothing to redo".to_owned()));
+        assert_eq!(repl.execute("eval abilities.double"), Ok("8".to_owned()));
+
+        // Expressions find paths from the current node first
+        assert_eq!(repl.execute("cd abilities"), Ok(String::new()));
+        assert_eq!(repl.execute("set double mod * 3"), Ok("abilities.double = 12".to_owned()));
+        assert_eq!(repl.execute("set double abilities.mod"), Ok("abilities.double = 4".to_owned()));
+        assert_eq!(repl.execute("cd /"), Ok(String::new()));
+
+        let mut output = Vec::new();
+        repl.run("cd abilities\nhistory\n!1\n!0\npwd\nquit\n".as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("   1  cd abilities\n   2  history\n"));
+        assert!(output.contains("no command 0 in history"));
+        assert!(output.ends_with("peanut:/abilities> /abilities\npeanut:/abilities> "));
+    }
+
+    #[test]
+    fn tab_completion() {
+        let template = Template::load("group abilities\nleaf abilities.strength = 16\nleaf abilities.stamina = 12\n").unwrap();
+        let mut repl = Repl::new(template, None);
+
+        // Tab fills in as much as every option has in common, or lists the options if that's nothing
+        let mut output = Vec::new();
+        repl.run_keys("cd ab\tx\x7f\neval st\tr\t\n\x1b[Cpwd\n\x04".as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("peanut:/> cd abilitiesx\x08 \x08\n"));
+        assert!(output.contains("peanut:/abilities> eval st\nstamina  strength\npeanut:/abilities> eval strength\n16\n"));
+        assert!(output.ends_with("peanut:/abilities> pwd\n/abilities\npeanut:/abilities> \n"));
+    }
+
+    #[test]
+    fn history_recall() {
+        let template = Template::load("group abilities\nleaf abilities.strength = 16\n").unwrap();
+        let mut repl = Repl::new(template, None);
+
+        // Up and down replace the line with earlier commands, and going past the newest brings back what was typed
+        let mut output = Vec::new();
+        repl.run_keys("cd abilities\neval strength\n\x1b[A\x1b[A\x1b[B\npw\x1b[A\x1b[B\x1b[Bd\n\x04".as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("peanut:/abilities> \rpeanut:/abilities> eval strength\x1b[K\rpeanut:/abilities> cd abilities\x1b[K"));
+        assert!(output.contains("\rpeanut:/abilities> eval strength\x1b[K\n16\n"));
+        assert!(output.ends_with("\rpeanut:/abilities> pw\x1b[Kd\n/abilities\npeanut:/abilities> \n"));
+        assert_eq!(repl.execute("history"), Ok("   1  cd abilities\n   2  eval strength\n   3  eval strength\n   4  pwd".to_owned()));
+    }
+}
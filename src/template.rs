@@ -2,16 +2,56 @@ mod tree;
 mod leaf;
 mod handle;
 mod meta;
-
-use std::collections::HashMap;
+mod explain;
+mod cache;
+mod query;
+mod deps;
+mod modifier;
+mod trace;
+mod table;
+mod enums;
+mod error;
+mod navigate;
+mod render;
+mod parse;
+mod file;
+mod check;
+mod print;
+mod inherit;
+mod component;
+mod collection;
+mod history;
+mod transaction;
+mod diff;
+
+use std::sync::Arc;
 
 pub use tree::NodeTree;
 pub use leaf::*;
 pub use handle::Handle;
+pub use meta::{EditMetaError, PushCommonError};
+pub use explain::{Explanation, Step};
+pub use cache::EvalCache;
+pub use modifier::{Modifier, ModifierOp};
+pub use trace::{TraceEvent, TraceHook};
+pub use table::{Table, TableKey};
+pub use error::ErrorReport;
+pub use navigate::{Walk, WalkOrder};
+pub use print::TreeView;
+pub use parse::{ParseError, ParseErrorKind};
+pub use file::{LoadError, LoadErrorKind};
+pub use inherit::{Override, RebaseError};
+pub use component::InstanceError;
+pub use collection::{Collection, CollectionError};
+pub use transaction::TransactionError;
+pub use diff::{Change, Conflict, MergeError};
 
 /// A Node ID, used for referencing nodes
 pub type NodeId = usize;
 
+/// An ID no node is ever given, which `#id` references to missing nodes are read back as
+const DANGLING: NodeId = NodeId::MAX;
+
 /// The number type
 pub type Integer = isize;
 
@@ -19,9 +59,20 @@ pub type Integer = isize;
 #[derive(Clone, Debug)]
 pub struct Template {
     /// All nodes in the template by ID
-    nodes: HashMap<NodeId, (Node, String)>,
+    nodes: inherit::Nodes,
     /// The ID to use for the next ID. This will just increment
     next_id: NodeId,
+    /// Called with details about evaluation as it happens, for debugging
+    trace: Option<trace::Tracer>,
+    /// The template this one was derived from, as it was when this one was derived or last rebased
+    base: Option<Arc<Template>>,
+    /// Edits that can be undone
+    history: history::History,
+    /// Nodes whose dependents need to be invalidated once the current transaction is finished
+    pending_invalidation: Option<Vec<NodeId>>,
+    /// Nodes which find their dependencies by path, so they can be found again when the structure changes, boxed since
+    /// every collection schema holds a template
+    watches: Box<deps::Watches>,
 }
 
 /// A generic node
@@ -52,6 +103,8 @@ pub struct Leaf {
     pub cache_valid: bool,
     /// A deferred leaf is not evaluated until it is used by an action
     pub deferred: bool,
+    /// An optional leaf may be left without a value, which can be checked for with `Expr::Has` and `OpKind::Coalesce`
+    pub optional: bool,
     /// The direct parent of this node, if any
     pub parent: Option<NodeId>,
     /// Metadata attached to this node
@@ -111,6 +164,10 @@ pub struct Meta {
     pub data: Metadata,
     pub cached: Option<Value>,
     pub cache_valid: bool,
+    /// Nodes this node refers to
+    pub dependencies: Vec<NodeId>,
+    /// Nodes that refer to this node
+    pub dependents: Vec<NodeId>,
 }
 
 /// Types of metadata to tell the template what to make without making it yourself
@@ -121,6 +178,14 @@ pub enum MetadataStart {
     Ident,
     Concat,
     Constraint(Constraint),
+    Table,
+    Enum,
+    /// Creates a default with a value of 0
+    Default,
+    /// Creates a modifier with the given operation and a value of 0, using the name of the metanode as its source
+    Modifier(ModifierOp),
+    /// Creates an unkeyed collection with an empty schema
+    Collection,
 }
 
 /// Certain metadata variants can modify other nodes
@@ -132,24 +197,84 @@ pub enum Metadata {
     Common { inner: NodeId, value: Option<Value> },
     /// A common proxy to be added to leaves to denote that they are affected by a __common meta node
     CommonProxy { inner: NodeId, value: Option<Value> },
-    /// Contains a list of integer values, which will be added together to form the direct parent's value
+    /// Contains a list of named integer expressions, which will be added together to form the direct parent's value
     /// 
     /// Applicable to: Leaves
-    Sum(Vec<Integer>),
-    /// Contains the identifier belonging to its direct parent
+    Sum(Vec<Contribution>),
+    /// Contains the identifier belonging to its direct parent, in the form chosen by the mode
     /// 
     /// If used in a `__common` metanode, this will contain the identifier of the node it is being placed into
     /// 
     /// Applicable to: Any
-    Ident,
-    /// Concatenates strings
+    Ident(IdentMode),
+    /// Concatenates strings and integers into a single string
     /// 
-    /// Applicable to: Groups
-    Concat(Vec<Expr>),
+    /// Applicable to: Any
+    Concat(Vec<ConcatElement>),
     /// Constrains the value of its direct parent
     /// 
     /// Applicable to: Leaves
     Constraint(Constraint),
+    /// Changes the value of its direct parent after it has been evaluated
+    /// 
+    /// Applicable to: Leaves
+    Modifier(Modifier),
+    /// Maps keys to values, for use with `Expr::Lookup`
+    /// 
+    /// Applicable to: Any
+    Table(Table),
+    /// The names of every valid value for leaves with the kind `ValueKind::Enum` of this metanode
+    /// 
+    /// Applicable to: Any
+    Enum(Vec<String>),
+    /// The value of its direct parent whenever the parent doesn't have a value of its own
+    /// 
+    /// Applicable to: Leaves
+    Default(Expr),
+    /// Makes every child of its direct parent an item with the same structure, which can be added, removed and moved
+    /// 
+    /// Applicable to: Groups
+    Collection(Collection),
+}
+
+/// What an `Ident` metanode contains about the node it identifies
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IdentMode {
+    /// The name of the node
+    #[default]
+    Name,
+    /// The full dotted path of the node
+    Path,
+    /// The name of the node's parent
+    ParentName,
+    /// The position of the node among its siblings, starting from 0
+    Index,
+}
+
+/// A single named part of a `Sum`, such as a bonus from an item
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Contribution {
+    /// Where the contribution comes from, only one contribution per source can be in a sum
+    pub source: String,
+    pub expr: Expr,
+}
+
+/// A single part of a `Concat`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConcatElement {
+    /// Must evaluate to a string or an integer
+    pub expr: Expr,
+    /// How the element is written if it's an integer
+    pub format: IntFormat,
+}
+
+/// How an integer is turned into a string
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IntFormat {
+    /// Whether positive numbers should be written with a `+`
+    pub sign: bool,
+    /// The minimum number of digits, padded with zeroes
+    pub width: usize,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -170,12 +295,37 @@ pub enum AddNodeError {
     InvalidName,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemoveNodeError {
+    NotExists,
+    IsRoot,
+}
+
+/// The form references should be converted to by [`Template::resolve_paths`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RefForm {
+    /// `Expr::Reference`, which is fastest to evaluate but breaks if the referenced node is removed
+    Id,
+    /// `Expr::PathRef`, which is looked up every time and keeps working after structural edits
+    Path,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum EditLeafError {
     NotExists,
     NotLeaf,
+    /// The leaf holds an enum and the value isn't one of its variants
+    InvalidVariant,
+    /// The kind refers to a node that isn't an `Enum` metanode
+    NotEnum,
 }
 
+/// Why a node couldn't be evaluated
+/// 
+/// These errors only know the IDs of the nodes involved, so they're displayed with IDs like `node #4 has no value`.
+/// Anything shown to a user should go through [`Template::report_eval`] or [`Template::eval_leaf_report`] instead,
+/// which give an [`ErrorReport`] naming nodes by path like [`Template::check`] and transactions do, such as
+/// `abilities.mod: has no value`
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EvalError {
     NotALeaf(NodeId),
@@ -187,23 +337,73 @@ pub enum EvalError {
     InvalidType,
     MetaType(NodeId),
     MissingParent(NodeId),
+    /// The element at `index` in the `Concat` metanode `id` couldn't be turned into a string
+    ConcatElement { id: NodeId, index: usize, cause: Box<EvalError> },
+    /// An optional leaf has no value or default
+    MissingOptional(NodeId),
+    /// A lookup referred to a node that isn't a `Table` metanode
+    NotATable(NodeId),
+    /// The key isn't covered by any row of the table `id`
+    KeyNotInTable { id: NodeId, key: Value },
+    /// The value doesn't satisfy the `Constraint` metanode `id`, found by [`Template::check`]
+    ConstraintViolated { id: NodeId, value: Integer },
+    /// The value of a leaf holding the `Enum` metanode `id` isn't one of its variants, found by [`Template::check`]
+    InvalidVariant { id: NodeId, variant: String },
+    /// An integer was divided by zero
+    DivisionByZero,
+    /// An integer was raised to a negative power
+    NegativeExponent,
+    /// An integer operation gave a result too large to store
+    Overflow,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum EvalMetaStatus {
     Success(Value),
-    Ident,
+    Ident(IdentMode),
     WrongType,
-    InvalidConcatElement,
+    InvalidConcatElement(usize, EvalError),
     InternalEvalError(EvalError),
     MissingInfo,
 }
 
+impl IntFormat {
+    pub fn apply(&self, value: Integer) -> String {
+        let width = self.width;
+
+        if self.sign && value >= 0 {
+            format!("+{value:0width$}")
+        } else if value < 0 {
+            // The width shouldn't include the minus sign
+            format!("-{:0width$}", value.unsigned_abs())
+        } else {
+            format!("{value:0width$}")
+        }
+    }
+}
+
+impl From<Expr> for ConcatElement {
+    fn from(expr: Expr) -> Self {
+        ConcatElement { expr, format: IntFormat::default() }
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Template {
     pub fn new() -> Self {
         let mut template = Self {
-            nodes: HashMap::new(),
+            nodes: Default::default(),
             next_id: 1,
+            trace: None,
+            base: None,
+            history: Default::default(),
+            pending_invalidation: None,
+            watches: Default::default(),
         };
 
         let mother_group = Group {
@@ -221,7 +421,7 @@ impl Template {
 
     fn new_id(&mut self) -> NodeId {
         let id = self.next_id;
-        self.next_id += 1;
+        self.set_next_id(id + 1);
 
         id
     }
@@ -229,7 +429,7 @@ impl Template {
     fn add_child(&mut self, parent: NodeId, id: NodeId) -> Result<(), AddNodeError> {
         let common_inner: Option<NodeId>;
 
-        if let Some(parent) = self.nodes.get_mut(&parent) {
+        if let Some(parent) = self.node_mut(parent) {
             match parent.0 {
                 Node::Group(ref mut group) => {
                     group.children.push(id);
@@ -255,8 +455,8 @@ impl Template {
     }
 
     // TODO: Make a macro for the `add_*_to` methods
-    pub fn add_leaf_to(&mut self, name: &str, parent: NodeId, deferred: bool) -> Result<LeafHandle, AddNodeError> {
-        if let Some(_) = self.get_node_from(name, parent) {
+    pub fn add_leaf_to(&mut self, name: &str, parent: NodeId, deferred: bool) -> Result<LeafHandle<'_>, AddNodeError> {
+        if self.get_node_from(name, parent).is_some() {
             return Err(AddNodeError::NameConflict);
         }
 
@@ -264,6 +464,7 @@ impl Template {
             return Err(AddNodeError::InvalidName)
         }
         
+        let outermost = self.begin_edit();
         let id = self.new_id();
         let leaf = Leaf {
             id,
@@ -272,6 +473,7 @@ impl Template {
             cached: None,
             cache_valid: false,
             deferred,
+            optional: false,
             parent: Some(parent),
             metadata: Vec::new(),
             dependencies: Vec::new(),
@@ -279,7 +481,10 @@ impl Template {
         };
 
         self.add_child(parent, id)?;
-        self.nodes.insert(id, (Node::Leaf(leaf), name.to_owned()));
+        self.insert_node(id, (Node::Leaf(leaf), name.to_owned()));
+        // Nothing can refer to the new node yet, but it can be matched by aggregates and path references
+        self.refresh_watchers_of(id);
+        self.record_edit(outermost);
 
         let handle = LeafHandle {
             id,
@@ -289,8 +494,8 @@ impl Template {
         Ok(handle)
     }
 
-    pub fn add_group_to(&mut self, name: &str, parent: NodeId) -> Result<GroupHandle, AddNodeError> {
-        if let Some(_) = self.get_node_from(name, parent) {
+    pub fn add_group_to(&mut self, name: &str, parent: NodeId) -> Result<GroupHandle<'_>, AddNodeError> {
+        if self.get_node_from(name, parent).is_some() {
             return Err(AddNodeError::NameConflict);
         }
 
@@ -298,6 +503,7 @@ impl Template {
             return Err(AddNodeError::InvalidName)
         }
 
+        let outermost = self.begin_edit();
         let id = self.new_id();
         let group = Group {
             id,
@@ -308,7 +514,9 @@ impl Template {
         };
 
         self.add_child(parent, id)?;
-        self.nodes.insert(id, (Node::Group(group), name.to_owned()));
+        self.insert_node(id, (Node::Group(group), name.to_owned()));
+        self.refresh_watchers_of(id);
+        self.record_edit(outermost);
 
         let handle = GroupHandle {
             id,
@@ -318,8 +526,8 @@ impl Template {
         Ok(handle)
     }
 
-    pub fn add_meta_to(&mut self, name: &str, parent_id: NodeId, start: MetadataStart) -> Result<MetaHandle, AddNodeError> {
-        if let Some(_) = self.get_node_from(name, parent_id) {
+    pub fn add_meta_to(&mut self, name: &str, parent_id: NodeId, start: MetadataStart) -> Result<MetaHandle<'_>, AddNodeError> {
+        if self.get_node_from(name, parent_id).is_some() {
             return Err(AddNodeError::NameConflict);
         }
 
@@ -327,10 +535,11 @@ impl Template {
             return Err(AddNodeError::InvalidName)
         }
 
+        let outermost = self.begin_edit();
         let (data, inner_group) = match start {
             MetadataStart::Common => {
                 if let Some(parent) = self.get_group_by_id(parent_id) {
-                    if parent.common != None {
+                    if parent.common.is_some() {
                         return Err(AddNodeError::InvalidParent);
                     }
                 } else {
@@ -349,24 +558,29 @@ impl Template {
                 (Metadata::Common { inner: group.id, value: None }, Some(group))
             },
             MetadataStart::Sum => (Metadata::Sum(Vec::new()), None),
-            MetadataStart::Ident => (Metadata::Ident, None),
+            MetadataStart::Ident => (Metadata::Ident(IdentMode::Name), None),
             MetadataStart::Concat => (Metadata::Concat(Vec::new()), None),
             MetadataStart::Constraint(constraint) => (Metadata::Constraint(constraint), None),
+            MetadataStart::Table => (Metadata::Table(Table::default()), None),
+            MetadataStart::Enum => (Metadata::Enum(Vec::new()), None),
+            MetadataStart::Default => (Metadata::Default(0.into()), None),
+            MetadataStart::Modifier(op) => (Metadata::Modifier(Modifier::new(name, op, 0.into())), None),
+            MetadataStart::Collection => (Metadata::Collection(Collection::default()), None),
         };
 
         let id = self.new_id();
 
         let mut common_inner: Option<NodeId> = None;
 
-        let parent_id = if let Some(parent) = self.nodes.get_mut(&parent_id) {
+        let parent_id = if let Some(parent) = self.node_mut(parent_id) {
             match parent.0 {
                 Node::Group(ref mut group) => {
                     group.metadata.push(id);
-                    id
+                    parent_id
                 },
                 Node::Leaf(ref mut leaf) => {
                     leaf.metadata.push(id);
-                    id
+                    parent_id
                 },
                 Node::Meta(ref mut meta) => match &mut meta.data {
                     Metadata::Common { inner: group_id, value: _ } => {
@@ -393,12 +607,23 @@ impl Template {
             data,
             cached: None,
             cache_valid: false,
+            dependencies: Vec::new(),
+            dependents: Vec::new(),
         };
         
-        self.nodes.insert(id, (Node::Meta(meta), name.to_owned()));
+        self.insert_node(id, (Node::Meta(meta), name.to_owned()));
         if let Some(inner_group) = inner_group {
-            self.nodes.insert(inner_group.id, (Node::Group(inner_group), "[COMMON INNER]".to_owned()));
+            self.insert_node(inner_group.id, (Node::Group(inner_group), "[COMMON INNER]".to_owned()));
+        }
+
+        // Leaves depend on any modifiers attached to them
+        if self.get_leaf_by_id(parent_id).is_some() {
+            self.refresh_dependencies(parent_id);
+            self.invalidate_dependents(parent_id);
         }
+        // Like any new node it can be matched by aggregates and path references
+        self.refresh_watchers_of(id);
+        self.record_edit(outermost);
 
         let handle = MetaHandle {
             id,
@@ -408,6 +633,88 @@ impl Template {
         Ok(handle)
     }
 
+    /// Removes a node along with all of its children and metadata
+    /// 
+    /// Any `Expr::Reference` to a removed node will fail to evaluate, while an `Expr::PathRef` will find whichever
+    /// node takes its place
+    pub fn remove_node(&mut self, id: NodeId) -> Result<(), RemoveNodeError> {
+        if id == 0 {
+            return Err(RemoveNodeError::IsRoot);
+        }
+
+        let parent = match &self.nodes.get(&id).ok_or(RemoveNodeError::NotExists)?.0 {
+            Node::Leaf(leaf) => leaf.parent,
+            Node::Group(group) => group.parent,
+            Node::Meta(meta) => Some(meta.parent),
+        };
+
+        // Nodes added to a `__common` metanode are kept in its inner group, like in `add_child`
+        let parent = match parent.and_then(|parent| self.nodes.get(&parent)).map(|(node, _)| node) {
+            Some(Node::Meta(Meta { data: Metadata::Common { inner, .. }, .. })) => Some(*inner),
+            _ => parent,
+        };
+
+        // Anything that found the nodes by path or refers to them has to be looked at again
+        let removed = self.subtree(id);
+        let mut affected = self.watchers_of(id);
+        for id in &removed {
+            affected.extend(self.dependents_of(*id));
+        }
+        affected.retain(|id| !removed.contains(id));
+
+        let outermost = self.begin_edit();
+        match parent.and_then(|parent| self.node_mut(parent)).map(|(node, _)| node) {
+            Some(Node::Group(group)) => {
+                group.children.retain(|child| *child != id);
+                group.metadata.retain(|child| *child != id);
+
+                if group.common == Some(id) {
+                    group.common = None;
+                }
+            },
+            Some(Node::Leaf(leaf)) => leaf.metadata.retain(|child| *child != id),
+            _ => (),
+        }
+
+        for id in removed {
+            self.unlink(id);
+            self.take_node(id);
+        }
+
+        self.refresh_watchers(affected);
+        // The siblings after the node move back a place
+        let siblings = parent.and_then(|parent| self.get_group_by_id(parent)).map(|group| group.children.clone()).unwrap_or_default();
+        for sibling in siblings {
+            let attached = self.children_with(sibling, false);
+            self.invalidate_identifiers(attached);
+        }
+        self.record_edit(outermost);
+
+        Ok(())
+    }
+
+    /// Gets the IDs of a node and everything contained within it
+    fn subtree(&self, id: NodeId) -> Vec<NodeId> {
+        let mut out = vec![id];
+
+        match self.nodes.get(&id).map(|(node, _)| node) {
+            Some(Node::Group(group)) => {
+                for child in group.children.iter().chain(group.metadata.iter()) {
+                    out.extend(self.subtree(*child));
+                }
+            },
+            Some(Node::Leaf(leaf)) => {
+                for child in &leaf.metadata {
+                    out.extend(self.subtree(*child));
+                }
+            },
+            Some(Node::Meta(Meta { data: Metadata::Common { inner, .. }, .. })) => out.extend(self.subtree(*inner)),
+            _ => (),
+        }
+
+        out
+    }
+
     /// Gets the ID of the node found at `path` relative to `parent`
     pub fn get_node_from(&self, path: &str, parent: NodeId) -> Option<NodeId> {
         let (name, path, last) = if let Some((name, path)) = path.split_once(".") {
@@ -446,7 +753,9 @@ impl Template {
     }
 
     fn set_leaf_value(&mut self, id: NodeId, value: Value) -> Result<(), EditLeafError> {
-        let (node, _) = self.nodes.get_mut(&id).ok_or(EditLeafError::NotExists)?;
+        let value = self.check_enum_value(id, value)?;
+        let outermost = self.begin_edit();
+        let (node, _) = self.node_mut(id).ok_or(EditLeafError::NotExists)?;
         let node = match node {
             Node::Leaf(leaf) => Ok(leaf),
             _ => Err(EditLeafError::NotLeaf),
@@ -456,13 +765,25 @@ impl Template {
 
         node.value_kind = value_kind;
         node.value = Some(Expr::Literal(value));
+        self.refresh_dependencies(id);
+        self.invalidate_dependents(id);
+        self.record_edit(outermost);
 
         Ok(())
     }
 
     fn set_leaf_expr(&mut self, id: NodeId, expr: Expr) -> Result<(), EditLeafError> {
-        let value_kind = self.check_expr_type(&expr);
-        let (node, _) = self.nodes.get_mut(&id).ok_or(EditLeafError::NotExists)?;
+        let expr = match expr {
+            Expr::Literal(value) => Expr::Literal(self.check_enum_value(id, value)?),
+            expr => expr,
+        };
+        // Leaves holding an enum keep it, and `check` reports any expression that isn't one of its variants
+        let value_kind = match self.get_leaf_by_id(id).map(|leaf| leaf.value_kind) {
+            Some(kind @ ValueKind::Enum(_)) => kind,
+            _ => self.check_expr_type(&expr, id),
+        };
+        let outermost = self.begin_edit();
+        let (node, _) = self.node_mut(id).ok_or(EditLeafError::NotExists)?;
         let node = match node {
             Node::Leaf(leaf) => Ok(leaf),
             _ => Err(EditLeafError::NotLeaf),
@@ -470,11 +791,142 @@ impl Template {
 
         node.value_kind = value_kind;
         node.value = Some(expr);
+        self.refresh_dependencies(id);
+        self.invalidate_dependents(id);
+        self.record_edit(outermost);
+
+        Ok(())
+    }
+
+    /// Converts every reference in the template to the given form
+    /// 
+    /// Converting to `RefForm::Path` uses absolute paths. If any reference can't be converted nothing is changed
+    pub fn resolve_paths(&mut self, form: RefForm) -> Result<(), EvalError> {
+        let mut converted = Vec::new();
+
+        for id in self.nodes.keys() {
+            let mut exprs: Vec<Expr> = self.exprs_of(*id).into_iter().cloned().collect();
+
+            for expr in &mut exprs {
+                self.convert_refs(expr, *id, form)?;
+            }
+
+            // Only nodes that actually change are part of the edit
+            if exprs.iter().ne(self.exprs_of(*id)) {
+                converted.push((*id, exprs));
+            }
+        }
+
+        let outermost = self.begin_edit();
+        for (id, exprs) in converted {
+            for (old, new) in self.exprs_of_mut(id).into_iter().zip(exprs) {
+                *old = new;
+            }
+        }
+
+        self.refresh_all_dependencies();
+        self.invalidate_caches();
+        self.record_edit(outermost);
+
+        Ok(())
+    }
+
+    fn convert_refs(&self, expr: &mut Expr, origin: NodeId, form: RefForm) -> Result<(), EvalError> {
+        match expr {
+            Expr::Reference(id) if form == RefForm::Path => {
+                *expr = Expr::PathRef(self.path_of(*id).ok_or(EvalError::MissingDependency(*id))?);
+            },
+            Expr::PathRef(path) if form == RefForm::Id => {
+                let id = self.resolve_path(path, origin).ok_or_else(|| EvalError::MissingPathDependency(path.clone()))?;
+                *expr = Expr::Reference(id);
+            },
+            Expr::InfixOp(op) => {
+                self.convert_refs(&mut op.lhs, origin, form)?;
+                self.convert_refs(&mut op.rhs, origin, form)?;
+            },
+            Expr::Has(inner) => self.convert_refs(inner, origin, form)?,
+            Expr::Literal(Value::List(items)) => {
+                for item in items {
+                    self.convert_refs(item, origin, form)?;
+                }
+            },
+            Expr::Lookup(lookup) => self.convert_refs(&mut lookup.key, origin, form)?,
+            _ => (),
+        }
+
+        Ok(())
+    }
+
+    /// Gets every expression stored directly in a node
+    fn exprs_of(&self, id: NodeId) -> Vec<&Expr> {
+        match self.nodes.get(&id).map(|(node, _)| node) {
+            Some(Node::Leaf(leaf)) => leaf.value.iter().collect(),
+            Some(Node::Meta(Meta { data: Metadata::Concat(elements), .. })) => elements.iter().map(|e| &e.expr).collect(),
+            Some(Node::Meta(Meta { data: Metadata::Sum(contributions), .. })) => contributions.iter().map(|c| &c.expr).collect(),
+            Some(Node::Meta(Meta { data: Metadata::Modifier(modifier), .. })) => vec![&modifier.value],
+            Some(Node::Meta(Meta { data: Metadata::Default(expr), .. })) => vec![expr],
+            _ => Vec::new(),
+        }
+    }
+
+    fn exprs_of_mut(&mut self, id: NodeId) -> Vec<&mut Expr> {
+        match self.node_mut(id).map(|(node, _)| node) {
+            Some(Node::Leaf(leaf)) => leaf.value.iter_mut().collect(),
+            Some(Node::Meta(Meta { data: Metadata::Concat(elements), .. })) => elements.iter_mut().map(|e| &mut e.expr).collect(),
+            Some(Node::Meta(Meta { data: Metadata::Sum(contributions), .. })) => contributions.iter_mut().map(|c| &mut c.expr).collect(),
+            Some(Node::Meta(Meta { data: Metadata::Modifier(modifier), .. })) => vec![&mut modifier.value],
+            Some(Node::Meta(Meta { data: Metadata::Default(expr), .. })) => vec![expr],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Marks every cached value as stale, this must be done after any edit that could change a value
+    fn invalidate_caches(&mut self) {
+        // Only nodes with a valid cache are changed, so a derived template doesn't copy the rest out of its base
+        let cached: Vec<NodeId> = self.nodes.iter()
+            .filter(|(_, (node, _))| matches!(node, Node::Leaf(Leaf { cache_valid: true, .. }) | Node::Meta(Meta { cache_valid: true, .. })))
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in cached {
+            match self.nodes.get_mut(&id).map(|(node, _)| node) {
+                Some(Node::Leaf(leaf)) => leaf.cache_valid = false,
+                Some(Node::Meta(meta)) => meta.cache_valid = false,
+                _ => (),
+            }
+        }
+    }
+
+    /// Empties a leaf so it can be filled in again, as if it had just been added
+    fn reset_leaf(&mut self, id: NodeId) {
+        let outermost = self.begin_edit();
+        if let Some(leaf) = self.get_mut_leaf_by_id(id) {
+            leaf.value_kind = ValueKind::Undefined;
+            leaf.value = None;
+            leaf.deferred = false;
+            leaf.optional = false;
+        }
+
+        self.refresh_dependencies(id);
+        self.invalidate_dependents(id);
+        self.record_edit(outermost);
+    }
+
+    fn set_leaf_optional(&mut self, id: NodeId, optional: bool) -> Result<(), EditLeafError> {
+        let outermost = self.begin_edit();
+        let (node, _) = self.node_mut(id).ok_or(EditLeafError::NotExists)?;
+        let Node::Leaf(leaf) = node else {
+            return Err(EditLeafError::NotLeaf);
+        };
+
+        leaf.optional = optional;
+        self.invalidate_dependents(id);
+        self.record_edit(outermost);
 
         Ok(())
     }
 
-    fn check_expr_type(&self, expr: &Expr) -> ValueKind {
+    fn check_expr_type(&self, expr: &Expr, origin: NodeId) -> ValueKind {
         match expr {
             Expr::Literal(value) => value.into(),
             Expr::Reference(id) => {
@@ -484,13 +936,101 @@ impl Template {
                     ValueKind::Undefined
                 }
             }
+            Expr::PathRef(path) => match self.resolve_path(path, origin) {
+                Some(id) => self.check_expr_type(&Expr::Reference(id), origin),
+                None => ValueKind::Undefined,
+            },
+            Expr::Aggregate(_) => ValueKind::Integer,
+            Expr::Lookup(lookup) => match self.resolve_path(&lookup.table, origin).and_then(|id| self.get_meta_by_id(id)) {
+                Some(Meta { data: Metadata::Table(table), .. }) => table.value_kind(),
+                _ => ValueKind::Undefined,
+            },
             Expr::IdentRef(_) => ValueKind::String,
+            Expr::Has(_) => ValueKind::Integer,
+            Expr::InfixOp(op) if op.kind == OpKind::Coalesce => self.check_expr_type(&op.lhs, origin),
             Expr::InfixOp(op) => {
                 (&**op).into()
             }
         }
     }
 
+    /// Reconstructs the dotted path of a node from its parent links
+    /// 
+    /// Nodes inside a `Common` metanode are reached through the metanode, and the root's path is empty
+    pub fn path_of(&self, id: NodeId) -> Option<String> {
+        let mut names = Vec::new();
+        let mut current = match self.nodes.get(&id)? {
+            // The inner group of a `__common` metanode is reached through the metanode itself
+            (Node::Group(_), name) if name == "[COMMON INNER]" => self.common_meta_of(id)?,
+            _ => id,
+        };
+
+        while current != 0 {
+            names.push(self.nodes.get(&current)?.1.as_str());
+            current = self.parent_of(current)?;
+        }
+
+        names.reverse();
+
+        Some(names.join("."))
+    }
+
+    /// Gets the node one level above `id` in its path
+    fn parent_of(&self, id: NodeId) -> Option<NodeId> {
+        let parent = match &self.nodes.get(&id)?.0 {
+            Node::Leaf(leaf) => leaf.parent?,
+            Node::Group(group) => group.parent?,
+            Node::Meta(meta) => meta.parent,
+        };
+
+        match self.nodes.get(&parent)? {
+            (Node::Group(_), name) if name == "[COMMON INNER]" => self.common_meta_of(parent),
+            _ => Some(parent),
+        }
+    }
+
+    /// Gets the ID of the node found at `path`, which may be relative to `origin`
+    /// 
+    /// `self` refers to `origin` and every `^` moves up one level, so `^.sibling` and `^^.uncle` are siblings of `origin`
+    /// and its parent. Any other path is resolved from the root
+    pub fn resolve_path(&self, path: &str, origin: NodeId) -> Option<NodeId> {
+        match self.split_relative(path, origin)? {
+            (base, Some(rest)) => self.get_node_from(rest, base),
+            (base, None) => Some(base),
+        }
+    }
+
+    /// Splits the relative prefix from a path, returning the node it refers to and the rest of the path
+    fn split_relative<'a>(&self, path: &'a str, origin: NodeId) -> Option<(NodeId, Option<&'a str>)> {
+        let (first, rest) = match path.split_once('.') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (path, None),
+        };
+
+        if first == "self" {
+            Some((origin, rest))
+        } else if !first.is_empty() && first.chars().all(|c| c == '^') {
+            let mut base = origin;
+
+            for _ in 0..first.len() {
+                base = self.parent_of(base)?;
+            }
+
+            Some((base, rest))
+        } else {
+            Some((0, Some(path)))
+        }
+    }
+
+    /// Finds the `__common` metanode which owns the inner group `inner`
+    fn common_meta_of(&self, inner: NodeId) -> Option<NodeId> {
+        let owner = self.get_group_by_id(inner)?.parent?;
+
+        self.get_group_by_id(owner)?.metadata.iter().copied().find(|id| {
+            matches!(self.get_meta_by_id(*id), Some(Meta { data: Metadata::Common { inner: group, .. }, .. }) if *group == inner)
+        })
+    }
+
     fn verify_name(&self, name: &str) -> bool {
         !name.contains('.')
     }
@@ -517,21 +1057,21 @@ impl Template {
     }
 
     fn get_mut_leaf_by_id(&mut self, id: NodeId) -> Option<&mut Leaf> {
-        match self.nodes.get_mut(&id)?.0 {
+        match self.node_mut(id)?.0 {
             Node::Leaf(ref mut leaf) => Some(leaf),
             _ => None,
         }
     }
 
     fn get_mut_group_by_id(&mut self, id: NodeId) -> Option<&mut Group> {
-        match self.nodes.get_mut(&id)?.0 {
+        match self.node_mut(id)?.0 {
             Node::Group(ref mut group) => Some(group),
             _ => None,
         }
     }
 
     fn get_mut_meta_by_id(&mut self, id: NodeId) -> Option<&mut Meta> {
-        match self.nodes.get_mut(&id)?.0 {
+        match self.node_mut(id)?.0 {
             Node::Meta(ref mut meta) => Some(meta),
             _ => None,
         }
@@ -542,120 +1082,227 @@ impl Template {
     }
 
     pub fn eval_leaf(&mut self, id: NodeId) -> Result<Value, EvalError> {
-        let mut checked = Vec::new();
-        let mut updates = Vec::new();
-        let out = self.eval_leaf_inner(id, &mut checked, &mut updates);
+        let mut cache = EvalCache::new();
+        let out = self.eval_leaf_cached(id, &mut cache)?;
+        self.store_cache(cache);
 
+        Ok(out)
+    }
 
-        if let Ok((out, updates)) = out.clone() {
-            // Get the leaf back so we can cache the output
-            if let Some(leaf) = self.get_mut_leaf_by_id(id) {
-                leaf.cached = Some(out);
-                leaf.cache_valid = true;
+    /// Gets the leaves back from an evaluation so we can cache the output
+    fn store_cache(&mut self, cache: EvalCache) {
+        // Caches aren't part of any edit, so this doesn't go through `node_mut`
+        for (id, value) in cache.values {
+            if let Some((Node::Leaf(node), _)) = self.nodes.get_mut(&id) {
+                node.cached = Some(value);
+                node.cache_valid = true;
             }
+        }
+    }
 
-            for (id, value) in updates {
-                if let Some(node) = self.get_mut_leaf_by_id(*id) {
-                    node.cached = Some(value.clone());
-                    node.cache_valid = true;
-                }
-            }
+    /// Evaluates a leaf without modifying the template
+    /// 
+    /// Valid caches stored in the template are still used, but any newly evaluated values are only stored in `cache`.
+    /// `cache` should be cleared whenever the template is edited
+    pub fn eval_leaf_cached(&self, id: NodeId, cache: &mut EvalCache) -> Result<Value, EvalError> {
+        cache.failure = None;
+
+        self.eval_leaf_inner(id, &mut Vec::new(), cache)
+    }
+
+    fn eval_leaf_inner(&self, id: NodeId, checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> Result<Value, EvalError> {
+        let out = self.eval_node(id, checked, cache);
+
+        // The innermost node to fail is the first to see the error, so it records where evaluation was at
+        if out.is_err() && cache.failure.is_none() {
+            cache.failure = Some(checked.iter().copied().chain([id]).collect());
         }
 
-        out.map(|(value, _)| value)
+        out
     }
 
-    fn eval_leaf_inner<'a>(&self, id: NodeId, checked: &mut Vec<NodeId>, updates: &'a mut Vec<(NodeId, Value)>) -> Result<(Value, &'a Vec<(NodeId, Value)>), EvalError> {
+    fn eval_node(&self, id: NodeId, checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> Result<Value, EvalError> {
         if checked.contains(&id) {
             return Err(EvalError::InfiniteRecursion(id));
         }
 
+        if let Some(cached) = cache.get(id) {
+            return Ok(cached.clone());
+        }
+
         let out = match &self.nodes.get(&id).ok_or(EvalError::MissingDependency(id))?.0 {
             Node::Leaf(leaf) => {
                 if leaf.cache_valid {
                     if let Some(cached) = &leaf.cached {
-                        return Ok((cached.clone(), updates));
+                        return Ok(cached.clone());
                     }
                 }
 
-                match &leaf.value {
-                    Some(expr) => self.eval_expr_inner(expr, checked),
-                    None => return Err(EvalError::MissingInfo(id)),
-                }
+                self.emit(TraceEvent::Eval { id });
+                checked.push(id);
+                let out = self.eval_leaf_base(leaf, checked, cache)
+                    .and_then(|base| self.apply_modifiers(leaf, base, checked, cache));
+                checked.pop();
+
+                out
             },
             Node::Group(_) => return Err(EvalError::NotALeaf(id)),
             Node::Meta(meta) => {
                 if meta.cache_valid {
                     if let Some(cached) = &meta.cached {
-                        return Ok((cached.clone(), updates));
+                        return Ok(cached.clone());
                     }
                 }
 
-                match self.eval_meta_inner(&meta.data, checked) {
-                    EvalMetaStatus::Success(value) => Ok(value),
-                    EvalMetaStatus::Ident => {
-                        let mut next = self.nodes.get(&meta.parent).ok_or(EvalError::MissingParent(meta.id))?;
+                self.emit(TraceEvent::Eval { id });
+                checked.push(id);
+                let status = self.eval_meta_inner(&meta.data, checked, cache);
+                checked.pop();
 
+                self.meta_status_to_result(meta, status)
+            },
+        }?;
 
-                        // The `__ident` meta node returns the name of the nearest non-meta parent node
-                        loop {
-                            println!("Ident ({})", next.1);
-                            if let (Node::Meta(inner), _) = next {
-                                next = self.nodes.get(&inner.parent).ok_or(EvalError::MissingParent(inner.id))?;
-                                continue;
-                            } else if let (Node::Group(inner), name) = next {
-                                if name == "[COMMON INNER]" {
-                                    next = self.nodes.get(&inner.parent.unwrap()).ok_or(EvalError::MissingParent(inner.id))?;
-                                    continue;
-                                }
+        cache.values.insert(id, out.clone());
 
-                                break;
-                            }
+        Ok(out)
+    }
 
-                            break;
-                        }
+    /// Evaluates the value of a leaf before any modifiers, falling back to its default if it has no value
+    fn eval_leaf_base(&self, leaf: &Leaf, checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> Result<Value, EvalError> {
+        match (&leaf.value, self.default_of(leaf)) {
+            (Some(expr), _) => self.eval_expr_inner(expr, checked, cache),
+            (None, Some(default)) => self.eval_leaf_inner(default, checked, cache),
+            (None, None) if leaf.optional => Err(EvalError::MissingOptional(leaf.id)),
+            (None, None) => Err(EvalError::MissingInfo(leaf.id)),
+        }
+    }
 
-                        Ok(Value::String(next.1.clone()))
-                    }
-                    EvalMetaStatus::WrongType => Err(EvalError::MetaType(id)),
-                    EvalMetaStatus::InvalidConcatElement => Err(EvalError::InvalidType),
-                    EvalMetaStatus::InternalEvalError(err) => Err(err),
-                    EvalMetaStatus::MissingInfo => Err(EvalError::MissingInfo(id)),
-                }
-            },
-        }?;
+    /// Gets the ID of the `Default` metanode attached to a leaf, if any
+    fn default_of(&self, leaf: &Leaf) -> Option<NodeId> {
+        leaf.metadata.iter().copied().find(|id| matches!(self.get_meta_by_id(*id), Some(Meta { data: Metadata::Default(_), .. })))
+    }
+
+    /// Converts the status of a metanode evaluation into its final value
+    fn meta_status_to_result(&self, meta: &Meta, status: EvalMetaStatus) -> Result<Value, EvalError> {
+        match status {
+            EvalMetaStatus::Success(value) => Ok(value),
+            EvalMetaStatus::Ident(mode) => self.eval_ident(meta, mode),
+            EvalMetaStatus::WrongType => Err(EvalError::MetaType(meta.id)),
+            EvalMetaStatus::InvalidConcatElement(index, cause) => Err(EvalError::ConcatElement { id: meta.id, index, cause: Box::new(cause) }),
+            EvalMetaStatus::InternalEvalError(err) => Err(err),
+            EvalMetaStatus::MissingInfo => Err(EvalError::MissingInfo(meta.id)),
+        }
+    }
+
+    fn eval_ident(&self, meta: &Meta, mode: IdentMode) -> Result<Value, EvalError> {
+        let mut next_id = meta.parent;
+
+        // The `__ident` meta node identifies the nearest non-meta parent node
+        let target = loop {
+            let next = self.nodes.get(&next_id).ok_or(EvalError::MissingParent(meta.id))?;
+            self.emit(TraceEvent::IdentStep { meta: meta.id, node: next_id, name: &next.1 });
 
-        updates.push((id, out.clone()));
+            match next {
+                (Node::Meta(inner), _) => next_id = inner.parent,
+                (Node::Group(inner), name) if name == "[COMMON INNER]" => {
+                    next_id = inner.parent.ok_or(EvalError::MissingParent(inner.id))?;
+                },
+                _ => break next_id,
+            }
+        };
+
+        match mode {
+            IdentMode::Name => Ok(Value::String(self.nodes[&target].1.clone())),
+            IdentMode::Path => self.path_of(target).map(Value::String).ok_or(EvalError::MissingParent(target)),
+            IdentMode::ParentName => {
+                let parent = self.parent_of(target).ok_or(EvalError::MissingParent(target))?;
 
-        Ok((out, &*updates))
+                Ok(Value::String(self.nodes[&parent].1.clone()))
+            },
+            IdentMode::Index => {
+                let parent = match &self.nodes[&target].0 {
+                    Node::Leaf(leaf) => leaf.parent,
+                    Node::Group(group) => group.parent,
+                    Node::Meta(_) => None,
+                };
+                let siblings = parent.and_then(|parent| self.get_group_by_id(parent)).ok_or(EvalError::MissingParent(target))?;
+                let index = siblings.children.iter().position(|id| *id == target).ok_or(EvalError::MissingParent(target))?;
+
+                Ok(Value::Integer(index as Integer))
+            },
+        }
     }
 
     pub fn eval_expr(&self, expr: &Expr) -> Result<Value, EvalError> {
-        let mut checked = Vec::new();
-        
-        self.eval_expr_inner(expr, &mut checked)
+        self.eval_expr_inner(expr, &mut Vec::new(), &mut EvalCache::new())
     }
 
-    fn eval_expr_inner(&self, expr: &Expr, checked: &mut Vec<NodeId>) -> Result<Value, EvalError> {
+    fn eval_expr_inner(&self, expr: &Expr, checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> Result<Value, EvalError> {
         match expr {
-            Expr::Literal(literal) => return Ok(literal.clone()),
-            Expr::Reference(ref_id) => self.eval_leaf_inner(*ref_id, checked, &mut Vec::new()).map(|(value, _)| value),
+            Expr::Literal(literal) => Ok(literal.clone()),
+            Expr::Reference(ref_id) => self.eval_leaf_inner(*ref_id, checked, cache),
+            Expr::PathRef(path) => {
+                let referenced_id = self.resolve_path_ref(path, checked)?;
+
+                self.eval_leaf_inner(referenced_id, checked, cache)
+            },
             Expr::IdentRef(ref_id) => {
-                let referenced_path = self.eval_leaf_inner(*ref_id, checked, &mut Vec::new()).map(|(value, _)| value)?;
-                
-                if let Value::String(name) = referenced_path {                                    
-                    let referenced_id = self.get_node_from(&name, 0).ok_or(EvalError::MissingPathDependency(name.to_owned()))?;
+                let referenced_id = self.resolve_ident_ref(*ref_id, checked, cache)?;
 
-                    self.eval_leaf_inner(referenced_id, checked, &mut Vec::new()).map(|(value, _)| value)
-                } else {
-                    Err(EvalError::InvalidIdentRef(*ref_id))
-                }
+                self.eval_leaf_inner(referenced_id, checked, cache)
+            },
+            Expr::Aggregate(aggregate) => self.eval_aggregate(aggregate, checked, cache),
+            Expr::Lookup(lookup) => {
+                let key = self.eval_expr_inner(&lookup.key, checked, cache)?;
+
+                self.eval_lookup(&lookup.table, key, checked).map(|(_, value)| value)
             },
-            Expr::InfixOp(expr) => expr.eval(self),
+            Expr::Has(inner) => match self.eval_expr_inner(inner, checked, cache) {
+                Ok(_) => Ok(Value::Integer(1)),
+                Err(EvalError::MissingOptional(_)) => {
+                    cache.failure = None;
+
+                    Ok(Value::Integer(0))
+                },
+                Err(err) => Err(err),
+            },
+            Expr::InfixOp(op) if op.kind == OpKind::Coalesce => match self.eval_expr_inner(&op.lhs, checked, cache) {
+                Err(EvalError::MissingOptional(_)) => {
+                    cache.failure = None;
+
+                    self.eval_expr_inner(&op.rhs, checked, cache)
+                },
+                out => out,
+            },
+            Expr::InfixOp(op) => {
+                let lhs = self.eval_expr_inner(&op.lhs, checked, cache)?;
+                let rhs = self.eval_expr_inner(&op.rhs, checked, cache)?;
+
+                op.apply(lhs, rhs)
+            },
+        }
+    }
+
+    /// Finds the node named by the path contained in the node referenced by an `IdentRef`
+    fn resolve_ident_ref(&self, ref_id: NodeId, checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> Result<NodeId, EvalError> {
+        let referenced_path = self.eval_leaf_inner(ref_id, checked, cache)?;
+
+        if let Value::String(name) = referenced_path {
+            self.resolve_path_ref(&name, checked)
+        } else {
+            Err(EvalError::InvalidIdentRef(ref_id))
         }
     }
 
-    fn eval_meta_inner(&self, meta: &Metadata, checked: &mut Vec<NodeId>) -> EvalMetaStatus {
+    /// Resolves a path against the node currently being evaluated
+    fn resolve_path_ref(&self, path: &str, checked: &[NodeId]) -> Result<NodeId, EvalError> {
+        let origin = checked.last().copied().unwrap_or(0);
+
+        self.resolve_path(path, origin).ok_or_else(|| EvalError::MissingPathDependency(path.to_owned()))
+    }
+
+    fn eval_meta_inner(&self, meta: &Metadata, checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> EvalMetaStatus {
         match meta {
             Metadata::Common { inner: _, value } => match value {
                 Some(value) => EvalMetaStatus::Success(value.clone()),
@@ -665,25 +1312,47 @@ impl Template {
                 Some(value) => EvalMetaStatus::Success(value.clone()),
                 None => EvalMetaStatus::MissingInfo,
             }
-            Metadata::Sum(elements) => EvalMetaStatus::Success(Value::Integer(elements.iter().sum())),
-            Metadata::Ident => EvalMetaStatus::Ident,
-            Metadata::Concat(elements) => self.concat_meta(elements, checked),
-            Metadata::Constraint(_) => EvalMetaStatus::WrongType,
+            Metadata::Sum(contributions) => self.sum_meta(contributions, checked, cache),
+            Metadata::Ident(mode) => EvalMetaStatus::Ident(*mode),
+            Metadata::Concat(elements) => self.concat_meta(elements, checked, cache),
+            Metadata::Constraint(_) | Metadata::Table(_) | Metadata::Enum(_) | Metadata::Collection(_) => EvalMetaStatus::WrongType,
+            Metadata::Modifier(Modifier { value: expr, .. }) | Metadata::Default(expr) => match self.eval_expr_inner(expr, checked, cache) {
+                Ok(value) => EvalMetaStatus::Success(value),
+                Err(err) => EvalMetaStatus::InternalEvalError(err),
+            },
+        }
+    }
+
+    fn sum_meta(&self, contributions: &[Contribution], checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> EvalMetaStatus {
+        let mut sum = 0;
+
+        for contribution in contributions {
+            match self.eval_expr_inner(&contribution.expr, checked, cache) {
+                Ok(Value::Integer(value)) => match Integer::checked_add(sum, value) {
+                    Some(total) => sum = total,
+                    None => return EvalMetaStatus::InternalEvalError(EvalError::Overflow),
+                },
+                Ok(_) => return EvalMetaStatus::InternalEvalError(EvalError::InvalidType),
+                Err(err) => return EvalMetaStatus::InternalEvalError(err),
+            }
         }
+
+        EvalMetaStatus::Success(Value::Integer(sum))
     }
 
-    fn concat_meta(&self, elements: &Vec<Expr>, checked: &mut Vec<NodeId>) -> EvalMetaStatus {
+    fn concat_meta(&self, elements: &[ConcatElement], checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> EvalMetaStatus {
         let mut out: Vec<String> = Vec::with_capacity(elements.len());
         
-        for expr in elements {
-            match self.eval_expr_inner(expr, checked) {
+        for (index, element) in elements.iter().enumerate() {
+            match self.eval_expr_inner(&element.expr, checked, cache) {
                 Ok(value) => {
                     match value {
                         Value::String(value) => out.push(value),
-                        _ => return EvalMetaStatus::InvalidConcatElement,
+                        Value::Integer(value) => out.push(element.format.apply(value)),
+                        _ => return EvalMetaStatus::InvalidConcatElement(index, EvalError::InvalidType),
                     }
                 }
-                Err(err) => return EvalMetaStatus::InternalEvalError(err),
+                Err(err) => return EvalMetaStatus::InvalidConcatElement(index, err),
             }
         }
 
@@ -701,10 +1370,47 @@ mod tests {
         EditLeafError,
         Expr,
         InfixOp,
+        Integer,
         OpKind,
         Template,
         AddNodeError,
         NodeTree,
+        Step,
+        Value,
+        EvalCache,
+        EvalError,
+        MetadataStart,
+        Handle,
+        ErrorReport,
+        TransactionError,
+        Change,
+        MergeError,
+        Override,
+        Conflict,
+        RebaseError,
+        InstanceError,
+        Collection,
+        CollectionError,
+        Meta,
+        Metadata,
+        RefForm,
+        RemoveNodeError,
+        Aggregate,
+        AggregateKind,
+        Modifier,
+        ModifierOp,
+        ConcatElement,
+        IntFormat,
+        IdentMode,
+        TraceEvent,
+        Table,
+        Lookup,
+        ValueKind,
+        ParseError,
+        ParseErrorKind,
+        LoadError,
+        LoadErrorKind,
+        DANGLING,
     };
 
     #[test]
@@ -861,6 +1567,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn add_nested_groups() -> Result<(), AddNodeError> {
         let mut template = Template::new();
 
@@ -876,6 +1583,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn get_nested_group() -> Result<(), AddNodeError> {
         let mut template = Template::new();
 
@@ -897,6 +1605,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn add_deep_groups() -> Result<(), AddNodeError> {
         let mut template = Template::new();
 
@@ -916,6 +1625,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn find_deep_group() -> Result<(), AddNodeError> {
         let mut template = Template::new();
 
@@ -939,4 +1649,1199 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn explain_infix() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut score = template.add_leaf("score", false)?;
+        score.set_value(14.into()).unwrap();
+        let score_id = score.id;
+
+        let mut modifier = template.add_leaf("mod", false)?;
+        let expr = Expr::InfixOp(Box::new(InfixOp { lhs: Expr::Reference(score_id), rhs: 10.into(), kind: OpKind::Sub }));
+        modifier.set_expr(expr).unwrap();
+        let mod_id = modifier.id;
+
+        let explanation = template.explain(mod_id).unwrap();
+
+        assert_eq!(explanation.value, Value::Integer(4));
+        assert_eq!(explanation.step, Step::Leaf { id: mod_id, path: "mod".to_owned() });
+        assert_eq!(explanation.children[0].step, Step::InfixOp(OpKind::Sub));
+        assert_eq!(explanation.children[0].children[0].step, Step::Reference { id: score_id, path: "score".to_owned() });
+        assert_eq!(explanation.to_string(), "mod = 4\n  Sub = 4\n    -> score = 14\n      14\n    10\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn explain_nested_path() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut group = template.add_group("abilities")?;
+        let mut leaf = group.add_leaf("strength", false)?;
+        leaf.set_value(18.into()).unwrap();
+        let id = leaf.id;
+
+        let explanation = template.explain(id).unwrap();
+
+        assert_eq!(explanation.step, Step::Leaf { id, path: "abilities.strength".to_owned() });
+
+        Ok(())
+    }
+
+    #[test]
+    fn explain_metanode_once() {
+        use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+        let mut template = Template::load("leaf strength = 3\nleaf attack = 0\nsum attack.bonus \"strength\": strength, \"sword\": 1\n").unwrap();
+        let bonus = template.resolve_path("attack.bonus", 0).unwrap();
+        let evaluated = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&evaluated);
+        template.set_trace(Some(Arc::new(move |event: TraceEvent<'_>| if let TraceEvent::Eval { .. } = event {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })));
+
+        // The value comes from the explained contributions rather than evaluating them again
+        let explanation = template.explain(bonus).unwrap();
+        assert_eq!(explanation.value, Value::Integer(4));
+        assert_eq!(explanation.children.len(), 2);
+        assert_eq!(evaluated.load(Ordering::SeqCst), 0);
+
+        // So does the value of an aggregate
+        let mut template = Template::load("group abilities\nleaf abilities.strength = 3\nleaf abilities.wisdom = 2\nleaf total = sum(abilities.*)\nleaf known = count(abilities.*)\n").unwrap();
+        let (total, known) = (template.get_leaf("total").unwrap().id, template.get_leaf("known").unwrap().id);
+        let counter = Arc::clone(&evaluated);
+        template.set_trace(Some(Arc::new(move |event: TraceEvent<'_>| if let TraceEvent::Eval { .. } = event {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })));
+
+        let explanation = template.explain(total).unwrap();
+        assert_eq!(explanation.value, Value::Integer(5));
+        assert_eq!(explanation.children[0].children.len(), 2);
+        assert_eq!(template.explain(known).unwrap().value, Value::Integer(2));
+        assert_eq!(evaluated.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn template_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<Template>();
+        assert_send_sync::<EvalCache>();
+    }
+
+    #[test]
+    fn eval_shared_between_threads() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut base = template.add_leaf("base", false)?;
+        base.set_value(8.into()).unwrap();
+        let base_id = base.id;
+
+        let mut doubled = template.add_leaf("doubled", false)?;
+        let expr = Expr::InfixOp(Box::new(InfixOp { lhs: Expr::Reference(base_id), rhs: 2.into(), kind: OpKind::Mul }));
+        doubled.set_expr(expr).unwrap();
+        let doubled_id = doubled.id;
+
+        let template = &template;
+
+        std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4).map(|_| scope.spawn(move || {
+                let mut cache = EvalCache::new();
+                let out = template.eval_leaf_cached(doubled_id, &mut cache);
+
+                assert_eq!(cache.get(base_id), Some(&Value::Integer(8)));
+
+                out
+            })).collect();
+
+            for thread in threads {
+                assert_eq!(thread.join().unwrap(), Ok(Value::Integer(16)));
+            }
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn edit_invalidates_cache() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut base = template.add_leaf("base", false)?;
+        base.set_value(8.into()).unwrap();
+        let base_id = base.id;
+
+        let mut copy = template.add_leaf("copy", false)?;
+        copy.set_expr(Expr::Reference(base_id)).unwrap();
+        let copy_id = copy.id;
+
+        assert_eq!(template.eval_leaf(copy_id), Ok(Value::Integer(8)));
+
+        let mut base = template.get_leaf_handle("base").unwrap();
+        base.set_value(3.into()).unwrap();
+
+        assert_eq!(template.eval_leaf(copy_id), Ok(Value::Integer(3)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn relative_path_ref() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        template.add_leaf("level", false)?.set_value(5.into()).unwrap();
+
+        let mut group = template.add_group("abilities")?;
+        group.add_leaf("strength", false)?.set_value(16.into()).unwrap();
+
+        let mut nested = group.add_group("nested")?;
+        let mut sibling = nested.add_leaf("sibling", false)?;
+        sibling.set_expr(Expr::PathRef("^.strength".to_owned())).unwrap();
+        let sibling_id = sibling.id;
+
+        let mut uncle = nested.add_leaf("uncle", false)?;
+        uncle.set_expr(Expr::PathRef("^^^.level".to_owned())).unwrap();
+        let uncle_id = uncle.id;
+
+        let mut own = nested.add_leaf("own", false)?;
+        let mut bonus = own.add_meta("bonus", MetadataStart::Sum)?;
+        bonus.push_contribution("ring", 2.into()).unwrap();
+        bonus.push_contribution("feat", 1.into()).unwrap();
+        let mut own = template.get_leaf_handle("abilities.nested.own").unwrap();
+        own.set_expr(Expr::PathRef("self.bonus".to_owned())).unwrap();
+        let own_id = own.id;
+
+        assert_eq!(template.eval_leaf(sibling_id), Err(EvalError::MissingPathDependency("^.strength".to_owned())));
+        let mut sibling = template.get_leaf_handle("abilities.nested.sibling").unwrap();
+        sibling.set_expr(Expr::PathRef("^^.strength".to_owned())).unwrap();
+
+        assert_eq!(template.eval_leaf(sibling_id), Ok(Value::Integer(16)));
+        assert_eq!(template.eval_leaf(uncle_id), Ok(Value::Integer(5)));
+        assert_eq!(template.eval_leaf(own_id), Ok(Value::Integer(3)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn relative_ident_ref() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut group = template.add_group("stats")?;
+        group.add_leaf("speed", false)?.set_value(30.into()).unwrap();
+        let mut name = group.add_leaf("name", false)?;
+        name.set_value(Value::String("^.speed".to_owned())).unwrap();
+        let name_id = name.id;
+
+        let mut group = template.get_group_handle("stats").unwrap();
+        let mut lookup = group.add_leaf("lookup", false)?;
+        lookup.set_expr(Expr::IdentRef(name_id)).unwrap();
+
+        assert_eq!(lookup.eval(), Ok(Value::Integer(30)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_node() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut group = template.add_group("gorp")?;
+        group.add_leaf("gup", false)?;
+        let id = group.id;
+
+        assert_eq!(template.remove_node(id), Ok(()));
+        assert!(template.get_node("gorp").is_none());
+        assert!(template.get_node("gorp.gup").is_none());
+        assert_eq!(template.list_nodes().len(), 1);
+        assert_eq!(template.remove_node(id), Err(RemoveNodeError::NotExists));
+        assert_eq!(template.remove_node(0), Err(RemoveNodeError::IsRoot));
+
+        // Nodes in a `__common` metanode are kept in its inner group, so that's where they're removed from
+        let mut abilities = template.add_group("abilities")?;
+        let common = abilities.add_meta("mod", MetadataStart::Common)?.id;
+        let value = template.add_leaf_to("value", common, false)?.id;
+        let Some(Meta { data: Metadata::Common { inner, .. }, .. }) = template.get_meta_by_id(common) else {
+            panic!("not a common metanode");
+        };
+        let inner = *inner;
+
+        assert_eq!(template.remove_node(value), Ok(()));
+        assert!(template.get_group_by_id(inner).unwrap().metadata.is_empty());
+        assert_eq!(template.save(), "group abilities\ncommon abilities.mod\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn path_ref_survives_recreation() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut base = template.add_leaf("base", false)?;
+        base.set_value(4.into()).unwrap();
+        let base_id = base.id;
+
+        let mut by_id = template.add_leaf("by_id", false)?;
+        by_id.set_expr(Expr::Reference(base_id)).unwrap();
+        let by_id = by_id.id;
+
+        let mut by_path = template.add_leaf("by_path", false)?;
+        by_path.set_expr(Expr::PathRef("base".to_owned())).unwrap();
+        let by_path = by_path.id;
+
+        template.remove_node(base_id).unwrap();
+        template.add_leaf("base", false)?.set_value(7.into()).unwrap();
+
+        assert_eq!(template.eval_leaf(by_id), Err(EvalError::MissingDependency(base_id)));
+        assert_eq!(template.eval_leaf(by_path), Ok(Value::Integer(7)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn resolve_paths() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut group = template.add_group("gorp")?;
+        let base_id = group.add_leaf("base", false)?.id;
+        let mut copy = group.add_leaf("copy", false)?;
+        let expr = Expr::InfixOp(Box::new(InfixOp { lhs: Expr::PathRef("^.base".to_owned()), rhs: 1.into(), kind: OpKind::Add }));
+        copy.set_expr(expr).unwrap();
+
+        template.resolve_paths(RefForm::Id).unwrap();
+        let expected = Expr::InfixOp(Box::new(InfixOp { lhs: Expr::Reference(base_id), rhs: 1.into(), kind: OpKind::Add }));
+        assert_eq!(template.get_leaf("gorp.copy").unwrap().value, Some(expected));
+
+        template.resolve_paths(RefForm::Path).unwrap();
+        let expected = Expr::InfixOp(Box::new(InfixOp { lhs: Expr::PathRef("gorp.base".to_owned()), rhs: 1.into(), kind: OpKind::Add }));
+        assert_eq!(template.get_leaf("gorp.copy").unwrap().value, Some(expected));
+
+        let mut copy = template.get_leaf_handle("gorp.copy").unwrap();
+        copy.set_expr(Expr::PathRef("missing".to_owned())).unwrap();
+        assert_eq!(template.resolve_paths(RefForm::Id), Err(EvalError::MissingPathDependency("missing".to_owned())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn query_wildcards() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut abilities = template.add_group("abilities")?;
+        for name in ["strength", "dexterity"] {
+            abilities.add_group(name)?.add_leaf("mod", false)?;
+        }
+        let mut nested = abilities.add_group("nested")?;
+        nested.add_group("deeper")?.add_leaf("mod", false)?;
+
+        let paths = |pattern| template.query(pattern).into_iter().map(|(_, path)| path).collect::<Vec<_>>();
+
+        assert_eq!(paths("abilities.*.mod"), ["abilities.strength.mod", "abilities.dexterity.mod"]);
+        assert_eq!(paths("abilities.**.mod"), ["abilities.strength.mod", "abilities.dexterity.mod", "abilities.nested.deeper.mod"]);
+        assert_eq!(paths("abilities.nested.**"), ["abilities.nested.deeper", "abilities.nested.deeper.mod"]);
+        assert_eq!(paths("abilities.*.missing"), Vec::<String>::new());
+
+        Ok(())
+    }
+
+    #[test]
+    fn aggregate_sum() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut equipment = template.add_group("equipment")?;
+        for (name, weight) in [("sword", 3), ("shield", 6), ("rope", 10)] {
+            equipment.add_group(name)?.add_leaf("weight", false)?.set_value(weight.into()).unwrap();
+        }
+
+        let mut total = template.add_leaf("total", false)?;
+        total.set_expr(Expr::Aggregate(Aggregate { kind: AggregateKind::Sum, pattern: "equipment.*.weight".to_owned() })).unwrap();
+        assert_eq!(total.eval(), Ok(Value::Integer(19)));
+
+        let mut count = template.add_leaf("count", false)?;
+        count.set_expr(Expr::Aggregate(Aggregate { kind: AggregateKind::Count, pattern: "^.equipment.**".to_owned() })).unwrap();
+        assert_eq!(count.eval(), Ok(Value::Integer(3)));
+        let count = count.id;
+
+        // Adding nodes changes what the patterns match, even though nothing refers to them
+        let torch = template.get_group_handle("equipment").unwrap().add_group("torch")?.id;
+        assert_eq!(template.eval_leaf(count), Ok(Value::Integer(3)));
+        let torch_weight = template.add_leaf_to("weight", torch, false)?.id;
+        assert_eq!(template.eval_leaf(count), Ok(Value::Integer(4)));
+
+        // The new leaf has no value, so it's still counted but can't be summed
+        assert_eq!(template.eval_leaf(torch_weight), Err(EvalError::MissingInfo(torch_weight)));
+        assert!(template.eval_leaf(template.get_leaf("total").unwrap().id).is_err());
+
+        // Aggregates are only evaluated again when something they match changes or a node matching them is added
+        use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+        let mut template = Template::load("group equipment\nleaf equipment.sword = 3\nleaf equipment.rope = 10\nleaf total = sum(equipment.*)\nleaf speed = 30\n").unwrap();
+        let (total, speed, sword) = (template.get_leaf("total").unwrap().id, template.get_leaf("speed").unwrap().id, template.get_leaf("equipment.sword").unwrap().id);
+        let evaluated = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&evaluated);
+        template.set_trace(Some(Arc::new(move |event: TraceEvent<'_>| if let TraceEvent::Eval { .. } = event {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })));
+        // Checking in a transaction keeps every value cached, and gives how many were evaluated since the last check
+        let settle = |template: &mut Template| {
+            template.transaction(|_| Ok::<_, EditLeafError>(())).unwrap();
+            evaluated.swap(0, Ordering::SeqCst)
+        };
+        settle(&mut template);
+
+        template.set_leaf_value(speed, 25.into()).unwrap();
+        assert_eq!(settle(&mut template), 1);
+        template.set_leaf_value(sword, 4.into()).unwrap();
+        assert_eq!(settle(&mut template), 2);
+        template.add_leaf("shield", false)?.set_value(5.into()).unwrap();
+        assert_eq!(settle(&mut template), 1);
+        let equipment = template.get_group("equipment").unwrap().id;
+        template.add_leaf_to("torch", equipment, false)?.set_value(1.into()).unwrap();
+        assert_eq!(settle(&mut template), 2);
+        assert_eq!(template.eval_leaf(total), Ok(Value::Integer(15)));
+        template.remove_node(sword).unwrap();
+        assert_eq!(settle(&mut template), 1);
+        assert_eq!(template.eval_leaf(total), Ok(Value::Integer(11)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn sum_contributions() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut strength = template.add_leaf("strength", false)?;
+        strength.set_value(3.into()).unwrap();
+        let strength_id = strength.id;
+
+        let mut attack = template.add_leaf("attack", false)?;
+        let attack_id = attack.id;
+        let mut bonus = attack.add_meta("bonus", MetadataStart::Sum)?;
+        let bonus_id = bonus.id;
+        bonus.push_contribution("strength", Expr::Reference(strength_id)).unwrap();
+        bonus.push_contribution("sword", 1.into()).unwrap();
+
+        let mut attack = template.get_leaf_handle("attack").unwrap();
+        attack.set_expr(Expr::Reference(bonus_id)).unwrap();
+        assert_eq!(attack.eval(), Ok(Value::Integer(4)));
+        assert_eq!(template.dependents_of(strength_id), [bonus_id]);
+        assert_eq!(template.dependents_of(bonus_id), [attack_id]);
+
+        template.get_leaf_handle("strength").unwrap().set_value(5.into()).unwrap();
+        assert_eq!(template.eval_leaf(attack_id), Ok(Value::Integer(6)));
+
+        let mut bonus = template.get_meta_handle("attack.bonus").unwrap();
+        bonus.push_contribution("sword", 2.into()).unwrap();
+        assert_eq!(bonus.remove_contribution("strength"), Ok(Some(Expr::Reference(strength_id))));
+        assert_eq!(bonus.remove_contribution("strength"), Ok(None));
+        assert_eq!(template.eval_leaf(attack_id), Ok(Value::Integer(2)));
+        assert!(template.dependents_of(strength_id).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn leaf_modifiers() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut armor = template.add_leaf("armor", false)?;
+        armor.set_value(10.into()).unwrap();
+        let armor_id = armor.id;
+
+        let modifiers = [
+            Modifier::new("ring", ModifierOp::Add, 1.into()).with_category("deflection"),
+            Modifier::new("amulet", ModifierOp::Add, 2.into()).with_category("deflection"),
+            Modifier::new("shield", ModifierOp::Add, 2.into()),
+            Modifier::new("haste", ModifierOp::Multiply, 2.into()).with_priority(1),
+            Modifier::new("cap", ModifierOp::Cap, 25.into()).with_priority(2),
+        ];
+
+        for modifier in modifiers {
+            let mut armor = template.get_leaf_handle("armor").unwrap();
+            let mut meta = armor.add_meta(&modifier.source.clone(), MetadataStart::Modifier(modifier.op))?;
+            meta.set_value(Metadata::Modifier(modifier)).unwrap();
+        }
+
+        // (10 + 2 + 2) * 2, capped at 25
+        assert_eq!(template.eval_leaf(armor_id), Ok(Value::Integer(25)));
+
+        let mut cap = template.get_meta_handle("armor.cap").unwrap();
+        cap.set_value(Metadata::Modifier(Modifier::new("cap", ModifierOp::Cap, 30.into()).with_priority(2))).unwrap();
+        assert_eq!(template.eval_leaf(armor_id), Ok(Value::Integer(28)));
+
+        let explanation = template.explain(armor_id).unwrap();
+        assert_eq!(explanation.value, Value::Integer(28));
+        assert_eq!(explanation.children.len(), 5);
+        assert_eq!(explanation.children[1].step, Step::Modifier { source: "amulet".to_owned(), op: ModifierOp::Add });
+
+        let saved = template.save();
+        assert!(saved.contains("modifier armor.cap cap 30 from \"cap\" priority 2\n"));
+        assert_eq!(Template::load(&saved).unwrap().save(), saved);
+
+        Ok(())
+    }
+
+    #[test]
+    fn concat_on_leaf() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut bonus = template.add_leaf("bonus", false)?;
+        bonus.set_value(3.into()).unwrap();
+        let bonus_id = bonus.id;
+
+        let mut label = template.add_leaf("label", false)?;
+        let mut concat = label.add_meta("text", MetadataStart::Concat)?;
+        let concat_id = concat.id;
+        concat.set_value(Metadata::Concat(vec![
+            Expr::from("Bonus: ".to_owned()).into(),
+            ConcatElement { expr: Expr::Reference(bonus_id), format: IntFormat { sign: true, width: 2 } },
+        ])).unwrap();
+
+        assert_eq!(template.eval_leaf(concat_id), Ok(Value::String("Bonus: +03".to_owned())));
+
+        template.get_leaf_handle("bonus").unwrap().set_value(Value::Integer(-4)).unwrap();
+        assert_eq!(template.eval_leaf(concat_id), Ok(Value::String("Bonus: -04".to_owned())));
+
+        // A node that has been removed is sure not to exist
+        let missing = template.add_leaf("missing", false)?.id;
+        template.remove_node(missing).unwrap();
+
+        let mut concat = template.get_meta_handle("label.text").unwrap();
+        concat.set_value(Metadata::Concat(vec![
+            Expr::from("Bonus: ".to_owned()).into(),
+            Expr::Reference(missing).into(),
+        ])).unwrap();
+
+        assert_eq!(template.eval_leaf(concat_id), Err(EvalError::ConcatElement {
+            id: concat_id,
+            index: 1,
+            cause: Box::new(EvalError::MissingDependency(missing)),
+        }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn ident_modes() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut abilities = template.add_group("abilities")?;
+        abilities.add_leaf("strength", false)?;
+        let mut dexterity = abilities.add_leaf("dexterity", false)?;
+        let ident_id = dexterity.add_meta("ident", MetadataStart::Ident)?.id;
+
+        let modes = [
+            (IdentMode::Name, Value::String("dexterity".to_owned())),
+            (IdentMode::Path, Value::String("abilities.dexterity".to_owned())),
+            (IdentMode::ParentName, Value::String("abilities".to_owned())),
+            (IdentMode::Index, Value::Integer(1)),
+        ];
+
+        for (mode, expected) in modes {
+            template.get_meta_handle("abilities.dexterity.ident").unwrap().set_value(Metadata::Ident(mode)).unwrap();
+            assert_eq!(template.eval_leaf(ident_id), Ok(expected));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn trace_hook() -> Result<(), AddNodeError> {
+        use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+
+        let evaluated = Arc::new(AtomicUsize::new(0));
+
+        let mut template = Template::new();
+        let counter = Arc::clone(&evaluated);
+        template.set_trace(Some(Arc::new(move |event: TraceEvent<'_>| if let TraceEvent::Eval { .. } = event {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })));
+
+        let mut base = template.add_leaf("base", false)?;
+        base.set_value(1.into()).unwrap();
+        let base_id = base.id;
+
+        let mut copy = template.add_leaf("copy", false)?;
+        copy.set_expr(Expr::Reference(base_id)).unwrap();
+        copy.eval().unwrap();
+        copy.eval().unwrap();
+
+        // The second evaluation is cached
+        assert_eq!(evaluated.load(Ordering::SeqCst), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn table_lookup() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut rules = template.add_group("rules")?;
+        let mut table = rules.add_meta("proficiency", MetadataStart::Table)?;
+        let table_id = table.id;
+        table.set_value(Metadata::Table(Table::new()
+            .with_range(1, 4, 2.into())
+            .with_range(5, 8, 3.into())
+            .with_row(9.into(), 4.into())
+        )).unwrap();
+
+        let mut level = template.add_leaf("level", false)?;
+        level.set_value(6.into()).unwrap();
+        let level_id = level.id;
+
+        let mut bonus = template.add_leaf("bonus", false)?;
+        bonus.set_expr(Expr::Lookup(Box::new(Lookup { table: "rules.proficiency".to_owned(), key: Expr::Reference(level_id) }))).unwrap();
+        let bonus_id = bonus.id;
+        assert_eq!(bonus.eval(), Ok(Value::Integer(3)));
+
+        template.get_leaf_handle("level").unwrap().set_value(9.into()).unwrap();
+        assert_eq!(template.eval_leaf(bonus_id), Ok(Value::Integer(4)));
+
+        template.get_leaf_handle("level").unwrap().set_value(12.into()).unwrap();
+        assert_eq!(template.eval_leaf(bonus_id), Err(EvalError::KeyNotInTable { id: table_id, key: Value::Integer(12) }));
+
+        let mut bonus = template.get_leaf_handle("bonus").unwrap();
+        bonus.set_expr(Expr::Lookup(Box::new(Lookup { table: "level".to_owned(), key: 1.into() }))).unwrap();
+        assert_eq!(bonus.eval(), Err(EvalError::NotATable(level_id)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn enum_values() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut sizes = template.add_meta("sizes", MetadataStart::Enum)?;
+        let sizes_id = sizes.id;
+        let variants = ["small", "medium", "large"].map(str::to_owned).to_vec();
+        sizes.set_value(Metadata::Enum(variants.clone())).unwrap();
+
+        let mut size = template.add_leaf("size", false)?;
+        size.set_value(Value::String("medium".to_owned())).unwrap();
+        size.set_kind(ValueKind::Enum(sizes_id)).unwrap();
+        let size_id = size.id;
+
+        assert_eq!(size.options(), Some(&variants[..]));
+        assert_eq!(size.get_value(), Some(&Expr::Literal(Value::Enum { id: sizes_id, variant: "medium".to_owned() })));
+        assert_eq!(size.set_value(Value::String("huge".to_owned())).map(|_| ()), Err(EditLeafError::InvalidVariant));
+        assert_eq!(size.set_value(3.into()).map(|_| ()), Err(EditLeafError::InvalidVariant));
+        size.set_value(Value::String("large".to_owned())).unwrap();
+
+        let mut is_large = template.add_leaf("is_large", false)?;
+        let expr = Expr::InfixOp(Box::new(InfixOp { lhs: Expr::Reference(size_id), rhs: "large".to_owned().into(), kind: OpKind::Eq }));
+        is_large.set_expr(expr).unwrap();
+        assert_eq!(is_large.eval(), Ok(Value::Integer(1)));
+
+        let mut other = template.add_leaf("other", false)?;
+        assert_eq!(other.set_kind(ValueKind::Enum(size_id)).map(|_| ()), Err(EditLeafError::NotEnum));
+
+        // Expressions keep the enum, with literals checked like values and anything else checked by `check`
+        let mut size = template.get_leaf_handle("size").unwrap();
+        assert_eq!(size.set_expr("huge".to_owned().into()).map(|_| ()), Err(EditLeafError::InvalidVariant));
+        size.set_expr("small".to_owned().into()).unwrap();
+        assert_eq!(size.get_value(), Some(&Expr::Literal(Value::Enum { id: sizes_id, variant: "small".to_owned() })));
+        assert!(template.check().is_empty());
+
+        template.add_leaf("choice", false)?.set_value("huge".to_owned().into()).unwrap();
+        template.get_leaf_handle("size").unwrap().set_expr(Expr::PathRef("choice".to_owned())).unwrap();
+        assert_eq!(template.get_leaf("size").unwrap().value_kind, ValueKind::Enum(sizes_id));
+        let reports = template.check();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].error, EvalError::InvalidVariant { id: sizes_id, variant: "huge".to_owned() });
+        assert_eq!(Template::load(&template.save()).unwrap().check().len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn default_values() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut level = template.add_leaf("level", false)?;
+        level.set_value(3.into()).unwrap();
+        let level_id = level.id;
+
+        let mut speed = template.add_leaf("speed", false)?;
+        let speed_id = speed.id;
+        assert_eq!(speed.eval(), Err(EvalError::MissingInfo(speed_id)));
+
+        let mut default = speed.add_meta("default", MetadataStart::Default)?;
+        let expr = Expr::InfixOp(Box::new(InfixOp { lhs: Expr::Reference(level_id), rhs: 10.into(), kind: OpKind::Mul }));
+        default.set_value(Metadata::Default(expr)).unwrap();
+        assert_eq!(template.eval_leaf(speed_id), Ok(Value::Integer(30)));
+
+        template.get_leaf_handle("level").unwrap().set_value(4.into()).unwrap();
+        assert_eq!(template.eval_leaf(speed_id), Ok(Value::Integer(40)));
+
+        template.get_leaf_handle("speed").unwrap().set_value(25.into()).unwrap();
+        assert_eq!(template.eval_leaf(speed_id), Ok(Value::Integer(25)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn optional_leaves() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut shield = template.add_leaf("shield", false)?;
+        shield.set_optional(true).unwrap();
+        let shield_id = shield.id;
+        assert_eq!(shield.eval(), Err(EvalError::MissingOptional(shield_id)));
+
+        let mut has = template.add_leaf("has_shield", false)?;
+        has.set_expr(Expr::Has(Box::new(Expr::Reference(shield_id)))).unwrap();
+        let has_id = has.id;
+
+        let mut bonus = template.add_leaf("bonus", false)?;
+        let expr = Expr::InfixOp(Box::new(InfixOp { lhs: Expr::Reference(shield_id), rhs: 0.into(), kind: OpKind::Coalesce }));
+        bonus.set_expr(expr).unwrap();
+        let bonus_id = bonus.id;
+
+        assert_eq!(template.eval_leaf(has_id), Ok(Value::Integer(0)));
+        assert_eq!(template.eval_leaf(bonus_id), Ok(Value::Integer(0)));
+
+        template.get_leaf_handle("shield").unwrap().set_value(2.into()).unwrap();
+        assert_eq!(template.eval_leaf(has_id), Ok(Value::Integer(1)));
+        assert_eq!(template.eval_leaf(bonus_id), Ok(Value::Integer(2)));
+
+        // Required leaves still fail
+        let required = template.add_leaf("required", false)?.id;
+        let mut has = template.get_leaf_handle("has_shield").unwrap();
+        has.set_expr(Expr::Has(Box::new(Expr::Reference(required)))).unwrap();
+        assert_eq!(has.eval(), Err(EvalError::MissingInfo(required)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn error_reports() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut stats = template.add_group("stats")?;
+        let mut total = stats.add_leaf("total", false)?;
+        total.set_expr(Expr::PathRef("^.bonus".to_owned())).unwrap();
+        let total_id = total.id;
+        let mut stats = template.get_group_handle("stats").unwrap();
+        let mut bonus = stats.add_leaf("bonus", false)?;
+        bonus.set_expr(Expr::PathRef("^.missing".to_owned())).unwrap();
+
+        let report = template.eval_leaf_report(total_id).unwrap_err();
+        assert_eq!(report.error, EvalError::MissingPathDependency("^.missing".to_owned()));
+        assert_eq!(report.path.as_deref(), Some("stats.bonus"));
+        assert_eq!(report.chain, vec!["stats.total".to_owned(), "stats.bonus".to_owned()]);
+        assert_eq!(
+            report.to_string(),
+            "stats.bonus: no node found at `^.missing` (while evaluating stats.total -> stats.bonus)",
+        );
+
+        let stats_id = template.get_group("stats").unwrap().id;
+        let error = template.add_leaf_to("total", stats_id, false).map(|_| ()).unwrap_err();
+        assert_eq!(template.report_add(error, stats_id, "total").to_string(), "stats.total: a node with this name already exists");
+
+        Ok(())
+    }
+
+    #[test]
+    fn tree_navigation() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut abilities = template.add_group("abilities")?;
+        abilities.add_leaf("strength", false)?;
+        let mut common = abilities.add_meta("mod", MetadataStart::Common)?;
+        let inner_leaf = common.add_leaf("value", false)?.id;
+        template.add_leaf("level", false)?;
+
+        let abilities_id = template.get_group("abilities").unwrap().id;
+        let mod_id = template.resolve_path("abilities.mod", 0).unwrap();
+
+        assert_eq!(template.path_of(inner_leaf).as_deref(), Some("abilities.mod.value"));
+        assert_eq!(template.children(abilities_id).iter().map(|(name, _)| *name).collect::<Vec<_>>(), ["strength", "mod"]);
+        assert_eq!(template.children(mod_id), [("value", inner_leaf)]);
+        assert_eq!(template.ancestors(inner_leaf), [mod_id, abilities_id, 0]);
+
+        let depth_first: Vec<_> = template.depth_first(0).map(|(path, _)| path).collect();
+        assert_eq!(depth_first, ["", "abilities", "abilities.strength", "abilities.mod", "abilities.mod.value", "level"]);
+
+        let breadth_first: Vec<_> = template.breadth_first(0).map(|(path, _)| path).collect();
+        assert_eq!(breadth_first, ["", "abilities", "level", "abilities.strength", "abilities.mod", "abilities.mod.value"]);
+
+        let exposed: Vec<_> = template.depth_first(mod_id).with_common_inner(true).map(|(path, _)| path).collect();
+        assert_eq!(exposed, ["abilities.mod", "abilities.mod.[COMMON INNER]", "abilities.mod.value"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn tree_printer() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut abilities = template.add_group("abilities")?;
+        let mut strength = abilities.add_leaf("strength", false)?;
+        strength.set_value(16.into()).unwrap();
+        let strength_id = strength.id;
+        let mut modifier = template.add_leaf("mod", false)?;
+        let sub = Expr::InfixOp(Box::new(InfixOp { lhs: Expr::Reference(strength_id), rhs: 10.into(), kind: OpKind::Sub }));
+        modifier.set_expr(Expr::InfixOp(Box::new(InfixOp { lhs: sub, rhs: 2.into(), kind: OpKind::Div }))).unwrap();
+        let mut shield = template.add_leaf("shield", false)?;
+        shield.set_optional(true).unwrap();
+        shield.add_meta("size", MetadataStart::Enum)?.set_value(Metadata::Enum(vec!["small".to_owned(), "large".to_owned()])).unwrap();
+
+        let tree = template.tree().to_string();
+        assert!(tree.starts_with("abilities (group)\n  strength (leaf) = 16\n"));
+        assert!(tree.contains("mod (leaf) = (abilities.strength - 10) / 2\n"));
+        assert!(tree.contains("shield (leaf, optional)\n  size (enum) small | large\n"));
+
+        let tree = template.tree().with_values(true).to_string();
+        assert!(tree.contains("mod (leaf) = (abilities.strength - 10) / 2 => 3\n"));
+        assert!(tree.contains("shield (leaf, optional) => error: shield: is optional and has no value\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn render_and_parse() -> Result<(), AddNodeError> {
+        let mut template = Template::new();
+
+        let mut stats = template.add_group("stats")?;
+        let strength = stats.add_leaf("strength", false)?.id;
+        let mut stats = template.get_group_handle("stats").unwrap();
+        let odd = stats.add_leaf("odd name", false)?.id;
+        let mut size = template.add_leaf("size", false)?;
+        let size_meta = size.add_meta("kind", MetadataStart::Enum)?.id;
+
+        let op = |lhs, kind, rhs| Expr::InfixOp(Box::new(InfixOp { lhs, rhs, kind }));
+        let exprs = [
+            (op(op(Expr::Reference(strength), OpKind::Sub, 10.into()), OpKind::Div, 2.into()), "(stats.strength - 10) / 2"),
+            (op(Expr::Reference(strength), OpKind::Sub, op(1.into(), OpKind::Sub, (-2).into())), "stats.strength - (1 - -2)"),
+            (op(op(2.into(), OpKind::Pow, 3.into()), OpKind::Pow, 2.into()), "(2 ^ 3) ^ 2"),
+            (op(2.into(), OpKind::Pow, op(3.into(), OpKind::Pow, 2.into())), "2 ^ 3 ^ 2"),
+            (op(1.into(), OpKind::Sub, op(Expr::Reference(strength), OpKind::Neg, 0.into())), "1 - -stats.strength"),
+            (op(op(Expr::Reference(strength), OpKind::Add, 1.into()), OpKind::Neg, 0.into()), "-(stats.strength + 1)"),
+            (op(2.into(), OpKind::Neg, 0.into()), "-(2)"),
+            (op(Expr::PathRef("^.bonus".to_owned()), OpKind::Coalesce, Expr::Reference(odd)), "$^.bonus ?? stats.`odd name`"),
+            (Expr::Literal(Value::String("say \"hi\"\n".to_owned())), r#""say \"hi\"\n""#),
+            (Expr::Literal(Value::Enum { id: size_meta, variant: "large".to_owned() }), "size.kind::large"),
+            (Expr::IdentRef(strength), "@stats.strength"),
+            (Expr::Aggregate(Aggregate { kind: AggregateKind::Sum, pattern: "stats.**".to_owned() }), "sum(stats.**)"),
+            (Expr::Has(Box::new(Expr::Lookup(Box::new(Lookup { table: "tables.prof".to_owned(), key: 3.into() })))), "has(lookup(tables.prof, 3))"),
+            (Expr::Literal(Value::List(vec![1.into(), "a".to_owned().into()])), r#"[1, "a"]"#),
+        ];
+
+        for (expr, source) in exprs {
+            assert_eq!(template.render_expr(&expr), source);
+            assert_eq!(template.parse_expr(source), Ok(expr));
+        }
+
+        // A node that no longer exists is written by its ID, but the ID is never read back as a node
+        assert_eq!(template.render_expr(&Expr::Reference(99)), "#99");
+        assert_eq!(template.parse_expr("#99"), Ok(Expr::Reference(DANGLING)));
+        assert_eq!(template.parse_expr(&format!("#{strength}")), Ok(Expr::Reference(DANGLING)));
+
+        assert_eq!(template.parse_expr("1 +"), Err(ParseError { position: 3, kind: ParseErrorKind::UnexpectedEnd }));
+        assert_eq!(template.parse_expr("stats.dex"), Err(ParseError { position: 0, kind: ParseErrorKind::UnknownPath("stats.dex".to_owned()) }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn save_and_load() {
+        let source = "\
+group abilities
+leaf abilities.strength = 16
+constraint abilities.strength.limit <= 20
+modifier abilities.strength.belt add 2 from \"belt\" priority 1 category \"enhancement\"
+common abilities.mod
+leaf abilities.mod.value = (abilities.strength - 10) / 2
+leaf body kind size = size::medium
+leaf shield optional
+default shield.fallback = 1
+leaf ac = 10 + abilities.mod.value + ($shield ?? 0)
+enum size small | medium | large
+table prof 1..=4 => 2, 5..=8 => 3
+sum total \"base\": 1, \"bonus\": lookup(prof, 5)
+concat label \"STR \", abilities.mod.value signed pad 2
+";
+
+        let mut template = Template::load(source).unwrap();
+        assert_eq!(template.save(), source);
+
+        let ac = template.get_leaf("ac").unwrap().id;
+        assert_eq!(template.eval_leaf(ac), Ok(Value::Integer(15)));
+        assert!(template.check().is_empty());
+
+        template.get_leaf_handle("abilities.strength").unwrap().set_value(20.into()).unwrap();
+        let reports = template.check();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].to_string(), "abilities.strength.limit: 22 doesn't satisfy the constraint");
+
+        let error = Template::load("group a\nleaf a.b = c\n").unwrap_err();
+        assert_eq!(error, LoadError { line: 2, kind: LoadErrorKind::Parse(ParseError { position: 11, kind: ParseErrorKind::UnknownPath("c".to_owned()) }) });
+
+        // A reference to a removed node stays broken after loading, even once its ID is given to another node
+        let mut template = Template::load("leaf old = 1\nleaf copy = old\n").unwrap();
+        let old = template.get_leaf("old").unwrap().id;
+        template.remove_node(old).unwrap();
+        let mut loaded = Template::load(&template.save()).unwrap();
+        for name in ["a", "b", "c"] {
+            loaded.add_leaf_to(name, 0, false).unwrap().set_value(2.into()).unwrap();
+        }
+        let copy = loaded.get_leaf("copy").unwrap().id;
+        assert_eq!(loaded.eval_leaf(copy), Err(EvalError::MissingDependency(DANGLING)));
+    }
+
+    #[test]
+    fn derive_and_rebase() {
+        let mut base = Template::load("group abilities\nleaf abilities.strength = 10\nleaf abilities.dexterity = 10\nleaf speed = 30\n").unwrap();
+        let mut fighter = base.derive();
+        assert!(fighter.overrides().is_empty());
+
+        fighter.get_leaf_handle("abilities.strength").unwrap().set_value(16.into()).unwrap();
+        fighter.add_leaf_to("armor", 0, false).unwrap().set_value(2.into()).unwrap();
+        assert_eq!(fighter.overrides(), [Override::Changed("abilities.strength".to_owned()), Override::Added("armor".to_owned())]);
+
+        // Nodes the fighter hasn't changed are read from its base instead of being copied
+        let stored = fighter.base().unwrap();
+        assert!(std::ptr::eq(fighter.get_leaf("speed").unwrap(), stored.get_leaf("speed").unwrap()));
+        assert!(!std::ptr::eq(fighter.get_leaf("abilities.strength").unwrap(), stored.get_leaf("abilities.strength").unwrap()));
+
+        // Unchanged nodes follow the base, while the fighter's own changes are kept along with every ID
+        let (strength, speed) = (fighter.get_leaf("abilities.strength").unwrap().id, fighter.get_leaf("speed").unwrap().id);
+        base.get_leaf_handle("speed").unwrap().set_value(25.into()).unwrap();
+        base.add_leaf_to("luck", 0, false).unwrap().set_value(1.into()).unwrap();
+        fighter.rebase(&base).unwrap();
+        assert_eq!(fighter.get_leaf("abilities.strength").unwrap().id, strength);
+        assert_eq!(fighter.get_leaf("speed").unwrap().id, speed);
+        for (path, value) in [("speed", 25), ("luck", 1), ("abilities.strength", 16), ("armor", 2)] {
+            let id = fighter.get_leaf(path).unwrap().id;
+            assert_eq!(fighter.eval_leaf(id), Ok(Value::Integer(value)));
+        }
+
+        // Only the nodes of the base are kept, not its own base or history
+        let champion = fighter.derive();
+        let stored = champion.base().unwrap();
+        assert!(stored.base().is_none());
+        assert!(!stored.clone().undo());
+        assert!(!fighter.base().unwrap().clone().undo());
+
+        base.get_leaf_handle("abilities.strength").unwrap().set_value(12.into()).unwrap();
+        assert_eq!(fighter.rebase(&base), Err(RebaseError::Conflicts(vec![Conflict::BothChanged("abilities.strength".to_owned())])));
+        assert_eq!(Template::new().rebase(&base), Err(RebaseError::NoBase));
+    }
+
+    #[test]
+    fn component_instances() {
+        let weapon = Template::load("leaf damage = 8\nleaf bonus = $proficiency\nleaf weight = 3\nleaf attack = bonus + 1\n").unwrap();
+        let mut template = Template::load("leaf proficiency = 2\ngroup inventory\n").unwrap();
+
+        template.add_instance("inventory", "longsword", &weapon).unwrap();
+        template.add_instance("inventory", "dagger", &weapon).unwrap()
+            .get_leaf_handle("weight").unwrap().set_value(1.into()).unwrap();
+        template.get_leaf_handle("proficiency").unwrap().set_value(3.into()).unwrap();
+
+        for (path, value) in [("inventory.longsword.weight", 3), ("inventory.dagger.weight", 1), ("inventory.dagger.attack", 4)] {
+            let id = template.get_leaf(path).unwrap().id;
+            assert_eq!(template.eval_leaf(id), Ok(Value::Integer(value)));
+        }
+
+        assert!(matches!(template.add_instance("inventory", "dagger", &weapon), Err(InstanceError::AddNode(AddNodeError::NameConflict))));
+        assert!(matches!(template.add_instance("stash", "axe", &weapon), Err(InstanceError::ParentNotFound(_))));
+    }
+
+    #[test]
+    fn collections() {
+        let schema = Template::load("leaf weight = 1\nleaf attuned = 0\n").unwrap();
+        let mut template = Template::load("group inventory\nleaf weight = sum(inventory.*.weight)\nleaf attuned = sum(inventory.*.attuned)\n").unwrap();
+        let inventory = template.get_group("inventory").unwrap().id;
+        template.add_meta_to("items", inventory, MetadataStart::Collection).unwrap()
+            .set_value(Metadata::Collection(Collection::new(schema))).unwrap();
+
+        for _ in 0..3 {
+            template.add_item(inventory, None).unwrap();
+        }
+        template.get_leaf_handle("inventory.1.weight").unwrap().set_value(5.into()).unwrap();
+        template.get_leaf_handle("inventory.2.attuned").unwrap().set_value(1.into()).unwrap();
+        let (weight, attuned) = (template.get_leaf("weight").unwrap().id, template.get_leaf("attuned").unwrap().id);
+        assert_eq!(template.eval_leaf(weight), Ok(Value::Integer(7)));
+        assert_eq!(template.eval_leaf(attuned), Ok(Value::Integer(1)));
+
+        // Items are renamed to match their new positions
+        template.remove_item(inventory, 0).unwrap();
+        template.move_item(inventory, 0, 1).unwrap();
+        assert_eq!(template.eval_leaf(weight), Ok(Value::Integer(6)));
+        assert_eq!(template.get_leaf("inventory.0.attuned").unwrap().value, Some(1.into()));
+        assert_eq!(template.get_leaf("inventory.1.weight").unwrap().value, Some(5.into()));
+        assert_eq!(template.remove_item(inventory, 2), Err(CollectionError::OutOfRange(2)));
+        assert!(matches!(template.add_item(inventory, Some("sword")), Err(CollectionError::NotKeyed)));
+
+        let mut loaded = Template::load(&template.save()).unwrap();
+        assert_eq!(loaded.save(), template.save());
+        let loaded_inventory = loaded.get_group("inventory").unwrap().id;
+        assert_eq!(loaded.add_item(loaded_inventory, None).unwrap().get_leaf("weight").unwrap().value, Some(1.into()));
+
+        // Unkeyed items carry on from the highest number, whatever else is in the collection
+        loaded.add_group_to("boss", loaded_inventory).unwrap();
+        let item = loaded.add_item(loaded_inventory, None).unwrap().id;
+        assert_eq!(loaded.path_of(item).as_deref(), Some("inventory.3"));
+        assert_eq!(loaded.items(loaded_inventory).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut template = Template::load("leaf strength = 10\nleaf mod = (strength - 10) / 2\n").unwrap();
+        assert!(!template.undo());
+        let (strength, modifier) = (template.get_leaf("strength").unwrap().id, template.get_leaf("mod").unwrap().id);
+        assert_eq!(template.eval_leaf(modifier), Ok(Value::Integer(0)));
+
+        template.checkpoint("start");
+        template.get_leaf_handle("strength").unwrap().set_value(16.into()).unwrap();
+        template.add_leaf_to("speed", 0, false).unwrap().set_value(30.into()).unwrap();
+        template.remove_node(modifier).unwrap();
+
+        // Each edit is undone on its own
+        assert!(template.undo());
+        assert_eq!(template.eval_leaf(modifier), Ok(Value::Integer(3)));
+        assert!(template.undo());
+        assert!(template.undo());
+        assert!(template.get_leaf("speed").is_none());
+        assert!(template.undo());
+        assert_eq!(template.eval_leaf(strength), Ok(Value::Integer(10)));
+
+        assert!(template.redo());
+        assert!(template.redo());
+        assert_eq!(template.get_leaf("speed").unwrap().value, None);
+        template.get_leaf_handle("speed").unwrap().set_value(25.into()).unwrap();
+        assert!(!template.redo());
+
+        assert!(template.restore("start"));
+        assert!(template.get_leaf("speed").is_none());
+        assert_eq!(template.eval_leaf(modifier), Ok(Value::Integer(0)));
+        assert!(template.undo());
+        assert_eq!(template.get_leaf("speed").unwrap().value, Some(25.into()));
+        assert!(!template.restore("missing"));
+        assert_eq!(template.checkpoints(), ["start"]);
+
+        template.set_history_limit(1);
+        assert!(template.undo());
+        assert!(!template.undo());
+
+        // A failed edit inside a larger edit only puts back its own changes
+        let before = template.eval_leaf(strength);
+        template.transaction(|tx| {
+            tx.get_leaf_handle("strength").unwrap().set_value(12.into())?;
+            let result = tx.transaction(|tx| {
+                tx.add_leaf_to("reach", 0, false).map_err(|_| EditLeafError::NotExists)?;
+                tx.get_leaf_handle("missing").ok_or(EditLeafError::NotExists).map(|_| ())
+            });
+            assert!(result.is_err());
+
+            Ok::<_, EditLeafError>(())
+        }).unwrap();
+        assert!(template.get_leaf("reach").is_none());
+        assert_eq!(template.eval_leaf(modifier), Ok(Value::Integer(1)));
+        assert!(template.undo());
+        assert_eq!(template.eval_leaf(strength), before);
+        assert!(template.add_leaf_to("reach", 0, false).is_ok());
+
+        // Aggregates follow nodes being added, undone and redone
+        let mut template = Template::load("group bag\nleaf items = count(bag.*)\nleaf tagged = count(bag.*.tag)\n").unwrap();
+        let (bag, items, tagged) = (template.get_group("bag").unwrap().id, template.get_leaf("items").unwrap().id, template.get_leaf("tagged").unwrap().id);
+        assert_eq!(template.eval_leaf(items), Ok(Value::Integer(0)));
+        assert_eq!(template.eval_leaf(tagged), Ok(Value::Integer(0)));
+
+        let rope = template.add_leaf_to("rope", bag, false).unwrap().id;
+        assert_eq!(template.eval_leaf(items), Ok(Value::Integer(1)));
+        let torch = template.add_group_to("torch", bag).unwrap().id;
+        assert_eq!(template.eval_leaf(tagged), Ok(Value::Integer(0)));
+        template.add_meta_to("tag", torch, MetadataStart::Default).unwrap();
+        assert_eq!(template.eval_leaf(tagged), Ok(Value::Integer(1)));
+        template.add_meta_to("tag", rope, MetadataStart::Default).unwrap();
+        assert_eq!(template.eval_leaf(tagged), Ok(Value::Integer(2)));
+
+        assert!(template.undo());
+        assert_eq!(template.eval_leaf(tagged), Ok(Value::Integer(1)));
+        assert!(template.undo());
+        assert_eq!(template.eval_leaf(tagged), Ok(Value::Integer(0)));
+        assert!(template.undo());
+        assert!(template.undo());
+        assert_eq!(template.eval_leaf(items), Ok(Value::Integer(0)));
+        assert!(template.redo());
+        assert_eq!(template.eval_leaf(items), Ok(Value::Integer(1)));
+        assert!(template.redo());
+        assert!(template.redo());
+        assert_eq!(template.eval_leaf(tagged), Ok(Value::Integer(1)));
+    }
+
+    #[test]
+    fn transactions() {
+        use std::{panic::{self, AssertUnwindSafe}, sync::{atomic::{AtomicUsize, Ordering}, Arc}};
+
+        let mut template = Template::load("leaf level = 1\nleaf hit_points = level * 8\nconstraint hit_points.cap <= 40\n").unwrap();
+        let (level, hit_points) = (template.get_leaf("level").unwrap().id, template.get_leaf("hit_points").unwrap().id);
+
+        template.transaction(|tx| {
+            tx.get_leaf_handle("level").unwrap().set_value(2.into())?;
+            tx.add_leaf_to("speed", 0, false)?.set_value(30.into())?;
+
+            Ok::<_, Box<dyn std::error::Error>>(())
+        }).unwrap();
+        assert_eq!(template.eval_leaf(hit_points), Ok(Value::Integer(16)));
+
+        // A broken constraint rolls back every edit
+        let result = template.transaction(|tx| {
+            tx.get_leaf_handle("level").unwrap().set_value(6.into())?;
+            tx.remove_node(tx.get_leaf("speed").unwrap().id).map_err(|_| EditLeafError::NotExists)
+        });
+        assert!(matches!(result, Err(TransactionError::Invalid(report)) if matches!(report.error, EvalError::ConstraintViolated { value: 48, .. })));
+        assert!(template.get_leaf("speed").is_some());
+
+        // So does a failed edit, and a cycle
+        let result = template.transaction(|tx| {
+            tx.get_leaf_handle("level").unwrap().set_value(3.into())?;
+            tx.get_leaf_handle("hit_points").unwrap().set_value("many".to_owned().into())?;
+            tx.get_leaf_handle("missing").ok_or(EditLeafError::NotExists).map(|_| ())
+        });
+        assert_eq!(result, Err(TransactionError::Edit(EditLeafError::NotExists)));
+        let result = template.transaction(|tx| tx.set_leaf_expr(level, Expr::Reference(hit_points)));
+        assert!(matches!(result, Err(TransactionError::Invalid(ErrorReport { error: EvalError::InfiniteRecursion(_), .. }))));
+        assert_eq!(template.eval_leaf(hit_points), Ok(Value::Integer(16)));
+
+        assert!(template.undo());
+        assert!(template.get_leaf("speed").is_none());
+        assert_eq!(template.eval_leaf(level), Ok(Value::Integer(1)));
+
+        // Values the edits don't change are only evaluated once
+        let mut template = Template::load("leaf level = 1\nleaf hit_points = level * 8\nleaf speed = 30\n").unwrap();
+        let (hit_points, speed) = (template.get_leaf("hit_points").unwrap().id, template.get_leaf("speed").unwrap().id);
+        let evaluated = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&evaluated);
+        template.set_trace(Some(Arc::new(move |event: TraceEvent<'_>| if let TraceEvent::Eval { .. } = event {
+            counter.fetch_add(1, Ordering::SeqCst);
+        })));
+
+        template.transaction(|tx| tx.set_leaf_value(speed, 25.into())).unwrap();
+        assert_eq!(evaluated.load(Ordering::SeqCst), 4);
+
+        // A panicking edit is rolled back without leaving history or invalidation paused
+        let result = panic::catch_unwind(AssertUnwindSafe(|| template.transaction(|tx| -> Result<(), EditLeafError> {
+            tx.get_leaf_handle("level").unwrap().set_value(5.into())?;
+            panic!("the edit went wrong");
+        })));
+        assert!(result.is_err());
+        assert_eq!(template.eval_leaf(hit_points), Ok(Value::Integer(8)));
+
+        template.get_leaf_handle("level").unwrap().set_value(2.into()).unwrap();
+        assert_eq!(template.eval_leaf(hit_points), Ok(Value::Integer(16)));
+        assert!(template.undo());
+        assert_eq!(template.eval_leaf(hit_points), Ok(Value::Integer(8)));
+        assert_eq!(template.eval_leaf(speed), Ok(Value::Integer(25)));
+    }
+
+    #[test]
+    fn diff_and_merge() {
+        let base = Template::load("group abilities\nleaf abilities.strength = 10\nleaf abilities.dexterity = 10\ngroup gear\nleaf gear.rope = 1\nleaf speed = 30\n").unwrap();
+        let rules = Template::load("group abilities\nleaf abilities.strength = 10\nleaf abilities.dexterity = 12\ngroup equipment\nleaf equipment.rope = 1\nleaf luck = 1\n").unwrap();
+
+        assert_eq!(base.diff(&rules), [
+            Change::Changed { path: "abilities.dexterity".to_owned(), before: "leaf abilities.dexterity = 10".to_owned(), after: "leaf abilities.dexterity = 12".to_owned() },
+            Change::Renamed { from: "gear".to_owned(), to: "equipment".to_owned() },
+            Change::Removed("speed".to_owned()),
+            Change::Added("luck".to_owned()),
+        ]);
+        assert!(base.diff(&base).is_empty());
+
+        // Renames are found however short the names are
+        let old = Template::load("group g\nleaf g.a = 1\nleaf l = 2\nleaf x = 3\n").unwrap();
+        let new = Template::load("group h\nleaf h.a = 1\nleaf e = 2\nleaf y = 4\n").unwrap();
+        assert_eq!(old.diff(&new), [
+            Change::Renamed { from: "g".to_owned(), to: "h".to_owned() },
+            Change::Renamed { from: "l".to_owned(), to: "e".to_owned() },
+            Change::Removed("x".to_owned()),
+            Change::Added("y".to_owned()),
+        ]);
+
+        // Our own edits are kept while the new rules are applied
+        let mut character = Template::load(&base.save()).unwrap();
+        let strength = character.get_leaf_handle("abilities.strength").unwrap().set_value(16.into()).unwrap().id;
+        let dexterity = character.get_leaf("abilities.dexterity").unwrap().id;
+        character.merge(&base, &rules).unwrap();
+        assert_eq!(character.save(), "group abilities\nleaf abilities.strength = 16\nleaf abilities.dexterity = 12\ngroup equipment\nleaf equipment.rope = 1\nleaf luck = 1\n");
+
+        // Nodes are changed in place, so the ones that are still there keep their IDs
+        assert_eq!(character.get_leaf("abilities.strength").unwrap().id, strength);
+        assert_eq!(character.get_leaf("abilities.dexterity").unwrap().id, dexterity);
+        assert_eq!(character.eval_leaf(dexterity), Ok(Value::Integer(12)));
+        assert!(character.undo());
+        assert!(character.get_leaf("speed").is_some());
+        assert_eq!(character.eval_leaf(dexterity), Ok(Value::Integer(10)));
+
+        // A node that changes kind is made again
+        let old = Template::load("leaf size = 1\nleaf reach = size\n").unwrap();
+        let new = Template::load("group size\nleaf size.value = 2\nleaf reach = size.value\n").unwrap();
+        let mut character = Template::load("leaf size = 1\nleaf reach = size\nleaf speed = 30\n").unwrap();
+        let speed = character.get_leaf("speed").unwrap().id;
+        character.merge(&old, &new).unwrap();
+        assert_eq!(character.save(), "leaf reach = size.value\nleaf speed = 30\ngroup size\nleaf size.value = 2\n");
+        assert_eq!(character.get_leaf("speed").unwrap().id, speed);
+        assert_eq!(character.eval_leaf(character.get_leaf("reach").unwrap().id), Ok(Value::Integer(2)));
+
+        let mut character = Template::load(&base.save()).unwrap();
+        character.get_leaf_handle("abilities.dexterity").unwrap().set_value(14.into()).unwrap();
+        character.remove_node(character.get_leaf("speed").unwrap().id).unwrap();
+        assert_eq!(character.merge(&base, &rules), Err(MergeError::Conflicts(vec![Conflict::BothChanged("abilities.dexterity".to_owned())])));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let mut template = Template::load("leaf divisor = 2\nleaf half = 4 / divisor\n").unwrap();
+        let half = template.get_leaf("half").unwrap().id;
+        assert_eq!(template.eval_leaf(half), Ok(Value::Integer(2)));
+
+        // Checking reports the problem and a transaction is rolled back because of it, rather than panicking
+        let result = template.transaction(|tx| tx.get_leaf_handle("divisor").unwrap().set_value(0.into()).map(|_| ()));
+        assert!(matches!(result, Err(TransactionError::Invalid(ErrorReport { error: EvalError::DivisionByZero, .. }))));
+        assert_eq!(template.eval_leaf(half), Ok(Value::Integer(2)));
+
+        template.get_leaf_handle("divisor").unwrap().set_value(0.into()).unwrap();
+        assert_eq!(template.eval_leaf(half), Err(EvalError::DivisionByZero));
+        let reports = template.check();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].to_string(), "half: division by zero");
+    }
+
+    #[test]
+    fn integer_overflow() {
+        let mut template = Template::new();
+        let mut big = template.add_leaf_to("big", 0, false).unwrap();
+        big.set_expr(Expr::InfixOp(Box::new(InfixOp { lhs: Integer::MAX.into(), rhs: 1.into(), kind: OpKind::Add }))).unwrap();
+        let big = big.id;
+        assert_eq!(template.eval_leaf(big), Err(EvalError::Overflow));
+
+        let mut doubled = template.add_leaf_to("doubled", 0, false).unwrap();
+        doubled.set_value(Value::Integer(Integer::MIN)).unwrap();
+        let doubled = doubled.id;
+        template.add_meta_to("twice", doubled, MetadataStart::Modifier(ModifierOp::Multiply)).unwrap()
+            .set_value(Metadata::Modifier(Modifier::new("twice", ModifierOp::Multiply, 2.into()))).unwrap();
+        assert_eq!(template.eval_leaf(doubled), Err(EvalError::Overflow));
+        assert_eq!(template.check().len(), 2);
+
+        let mut template = Template::load(&format!("leaf total = 0\nsum total.bonus \"a\": {}, \"b\": 1\n", Integer::MAX)).unwrap();
+        let bonus = template.resolve_path("total.bonus", 0).unwrap();
+        assert_eq!(template.eval_leaf(bonus), Err(EvalError::Overflow));
+        assert_eq!(template.explain(bonus), Err(EvalError::Overflow));
+    }
+
+    #[test]
+    fn power_and_negation() {
+        let template = Template::new();
+        let eval = |source: &str| template.eval_expr(&template.parse_expr(source).unwrap());
+
+        assert_eq!(eval("2 ^ 3"), Ok(Value::Integer(8)));
+        assert_eq!(eval("2 ^ 3 ^ 2"), Ok(Value::Integer(512)));
+        assert_eq!(eval("5 ^ 0"), Ok(Value::Integer(1)));
+        assert_eq!(eval("2 ^ -1"), Err(EvalError::NegativeExponent));
+        assert_eq!(eval("2 ^ 200"), Err(EvalError::Overflow));
+        assert_eq!(eval("-(2 ^ 3) + 10"), Ok(Value::Integer(2)));
+        assert_eq!(eval("--4"), Ok(Value::Integer(4)));
+        assert_eq!(eval("-\"a\""), Err(EvalError::InvalidType));
+    }
+}
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use super::{NodeId, Value};
+
+/// Values evaluated outside of the template, so evaluation doesn't need exclusive access to it
+/// 
+/// Each thread evaluating a shared template should use its own cache
+#[derive(Clone, Debug, Default)]
+pub struct EvalCache {
+    /// Evaluated values by node ID
+    pub(super) values: HashMap<NodeId, Value>,
+    /// The nodes being evaluated when the last evaluation failed, outermost first
+    pub(super) failure: Option<Vec<NodeId>>,
+}
+
+impl EvalCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the cached value of a node, if it has been evaluated
+    pub fn get(&self, id: NodeId) -> Option<&Value> {
+        self.values.get(&id)
+    }
+
+    /// Removes the cached value of a node
+    pub fn invalidate(&mut self, id: NodeId) {
+        self.values.remove(&id);
+    }
+
+    /// The IDs of the nodes that were being evaluated when the last evaluation failed, outermost first
+    pub fn failure_chain(&self) -> Option<&[NodeId]> {
+        self.failure.as_deref()
+    }
+
+    /// Removes every cached value, this should be done after any edit to the template
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.failure = None;
+    }
+}
@@ -0,0 +1,78 @@
+use super::{Constraint, ErrorReport, EvalCache, EvalError, Integer, Meta, Metadata, Node, Template, Value, ValueKind};
+
+impl Constraint {
+    /// Whether `value` satisfies the constraint
+    pub fn allows(&self, value: Integer) -> bool {
+        match *self {
+            Constraint::GreaterThan(bound) => value > bound,
+            Constraint::GreaterOrEqual(bound) => value >= bound,
+            Constraint::LessThan(bound) => value < bound,
+            Constraint::LessOrEqual(bound) => value <= bound,
+            Constraint::Equal(bound) => value == bound,
+        }
+    }
+}
+
+impl Template {
+    /// Evaluates every leaf, reporting anything that's wrong with the template
+    /// 
+    /// This finds cycles, values of the wrong type, references to nodes that don't exist, values outside of their
+    /// `Constraint` metanodes and enum leaves that aren't one of their variants. Leaves that just haven't been given a value yet aren't reported, and each error is
+    /// only reported once even if many leaves depend on it
+    pub fn check(&self) -> Vec<ErrorReport<EvalError>> {
+        self.check_cached(&mut EvalCache::new())
+    }
+
+    /// Checks the template like [`Template::check`], keeping every value that was evaluated in `cache`
+    pub(super) fn check_cached(&self, cache: &mut EvalCache) -> Vec<ErrorReport<EvalError>> {
+        let mut reports: Vec<ErrorReport<EvalError>> = Vec::new();
+
+        for (_, node) in self.depth_first(0) {
+            let Node::Leaf(leaf) = node else {
+                continue;
+            };
+
+            let error = match self.eval_leaf_cached(leaf.id, cache) {
+                Err(EvalError::MissingInfo(_) | EvalError::MissingOptional(_)) => continue,
+                Err(error) => error,
+                Ok(Value::Integer(value)) => {
+                    let violated = leaf.metadata.iter().find_map(|id| match self.get_meta_by_id(*id) {
+                        Some(Meta { data: Metadata::Constraint(constraint), .. }) if !constraint.allows(value) => Some(*id),
+                        _ => None,
+                    });
+
+                    match violated {
+                        Some(id) => EvalError::ConstraintViolated { id, value },
+                        None => continue,
+                    }
+                },
+                // Expressions can give anything, so enum leaves are only known to hold a variant once they're evaluated
+                Ok(Value::String(variant) | Value::Enum { variant, .. }) => match leaf.value_kind {
+                    ValueKind::Enum(id) if self.enum_variants(id).is_some_and(|variants| !variants.contains(&variant)) => {
+                        EvalError::InvalidVariant { id, variant }
+                    },
+                    _ => continue,
+                },
+                Ok(_) => continue,
+            };
+
+            let chain = cache.failure_chain().unwrap_or(&[leaf.id]).to_vec();
+            let new = self.report_eval(error, &chain);
+
+            // A cycle is found again from every leaf in it, and errors without a node are told apart by where they are
+            let duplicate = reports.iter().any(|report| match (&report.error, &new.error) {
+                (EvalError::InfiniteRecursion(_), EvalError::InfiniteRecursion(id)) => {
+                    report.error == new.error || self.path_of(*id).is_some_and(|path| report.chain.contains(&path))
+                },
+                (old, error) if error.node().is_some() => old == error,
+                _ => report.error == new.error && report.path == new.path,
+            });
+
+            if !duplicate {
+                reports.push(new);
+            }
+        }
+
+        reports
+    }
+}
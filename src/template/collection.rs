@@ -0,0 +1,145 @@
+use super::{AddNodeError, GroupHandle, LoadError, Meta, Metadata, Node, NodeId, Template};
+
+/// The structure shared by every item in a collection, such as the items in an inventory
+///
+/// A collection is a group with a `Collection` metanode, and every child of the group is an item made from the
+/// schema. Aggregates work over the items like any other children, so `sum(inventory.*.weight)` is the total weight
+/// and `sum(inventory.*.attuned)` counts the items whose `attuned` leaf is 1
+#[derive(Clone, Debug, Default)]
+pub struct Collection {
+    /// The nodes each item starts with, instantiated like [`Template::add_instance`]
+    pub schema: Template,
+    /// Whether items are named by a key given when they're added, rather than by their position
+    pub keyed: bool,
+}
+
+/// Why an item couldn't be added, removed or moved
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CollectionError {
+    /// The node isn't a group with a `Collection` metanode
+    NotCollection,
+    /// Items in a keyed collection need a key
+    KeyNeeded,
+    /// Items in an unkeyed collection are named by their position, so they can't be given a key
+    NotKeyed,
+    /// There's no item at the index
+    OutOfRange(usize),
+    AddNode(AddNodeError),
+    Load(LoadError),
+}
+
+impl Collection {
+    pub fn new(schema: Template) -> Self {
+        Collection { schema, keyed: false }
+    }
+
+    /// Names items by a key given when they're added
+    pub fn keyed(mut self) -> Self {
+        self.keyed = true;
+        self
+    }
+}
+
+impl Template {
+    /// Gets the collection described by a group's `Collection` metanode
+    pub fn collection_of(&self, group: NodeId) -> Option<&Collection> {
+        self.get_group_by_id(group)?.metadata.iter().find_map(|id| match self.get_meta_by_id(*id) {
+            Some(Meta { data: Metadata::Collection(collection), .. }) => Some(collection),
+            _ => None,
+        })
+    }
+
+    /// Gets the IDs of the items in a collection, in order
+    pub fn items(&self, collection: NodeId) -> Result<Vec<NodeId>, CollectionError> {
+        self.collection_of(collection).ok_or(CollectionError::NotCollection)?;
+
+        Ok(self.get_group_by_id(collection).map(|group| group.children.clone()).unwrap_or_default())
+    }
+
+    /// Adds an item made from the collection's schema to the end of a collection
+    ///
+    /// Items in a keyed collection are named by `key`, and items in other collections are numbered one past the highest
+    /// numbered item, starting from `0`
+    pub fn add_item(&mut self, collection: NodeId, key: Option<&str>) -> Result<GroupHandle<'_>, CollectionError> {
+        let Collection { schema, keyed } = self.collection_of(collection).ok_or(CollectionError::NotCollection)?;
+        let name = match (keyed, key) {
+            (true, Some(key)) => key.to_owned(),
+            (true, None) => return Err(CollectionError::KeyNeeded),
+            (false, Some(_)) => return Err(CollectionError::NotKeyed),
+            // Counting the items would skip or repeat numbers once anything else is in the collection
+            (false, None) => self.items(collection)?.into_iter()
+                .filter_map(|item| self.nodes.get(&item)?.1.parse::<usize>().ok())
+                .max()
+                .map_or(0, |last| last + 1)
+                .to_string(),
+        };
+        let schema = schema.save();
+
+        let id = self.single_edit(|template| {
+            let id = template.add_group_to(&name, collection).map_err(CollectionError::AddNode)?.id;
+            template.load_into(&schema, id).map_err(CollectionError::Load)?;
+
+            Ok(id)
+        })?;
+
+        Ok(GroupHandle { id, template: self })
+    }
+
+    /// Removes the item at `index` from a collection, moving the items after it back
+    pub fn remove_item(&mut self, collection: NodeId, index: usize) -> Result<(), CollectionError> {
+        let item = *self.items(collection)?.get(index).ok_or(CollectionError::OutOfRange(index))?;
+
+        self.single_edit(|template| {
+            template.remove_node(item).expect("the item is in the collection");
+            template.rename_items(collection);
+
+            Ok(())
+        })
+    }
+
+    /// Moves the item at `from` so it's at `to`, shifting the items in between
+    pub fn move_item(&mut self, collection: NodeId, from: usize, to: usize) -> Result<(), CollectionError> {
+        let count = self.items(collection)?.len();
+
+        for index in [from, to] {
+            if index >= count {
+                return Err(CollectionError::OutOfRange(index));
+            }
+        }
+
+        self.single_edit(|template| {
+            if let Some(group) = template.get_mut_group_by_id(collection) {
+                let item = group.children.remove(from);
+                group.children.insert(to, item);
+            }
+
+            template.rename_items(collection);
+
+            Ok(())
+        })
+    }
+
+    /// Renames the items of an unkeyed collection to match their positions
+    fn rename_items(&mut self, collection: NodeId) {
+        let items = self.items(collection).unwrap_or_default();
+        // Anything which found an item by its old name or finds one by its new name has to be looked at again
+        let mut watchers: Vec<NodeId> = items.iter().flat_map(|item| self.watchers_of(*item)).collect();
+
+        if self.collection_of(collection).is_some_and(|collection| !collection.keyed) {
+            for (index, item) in items.iter().enumerate() {
+                if let Some((Node::Group(_), name)) = self.node_mut(*item) {
+                    *name = index.to_string();
+                }
+            }
+        }
+
+        watchers.extend(items.iter().flat_map(|item| self.watchers_of(*item)));
+        self.refresh_watchers(watchers);
+
+        // Paths and positions both changed, so every identifier inside the items could be different
+        for item in items {
+            let inside = self.subtree(item);
+            self.invalidate_identifiers(inside);
+        }
+    }
+}
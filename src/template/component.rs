@@ -0,0 +1,44 @@
+use super::{AddNodeError, GroupHandle, LoadError, Meta, Metadata, Template};
+
+/// Why a component couldn't be instantiated
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InstanceError {
+    /// There's no node at the path the instance should be added to
+    ParentNotFound(String),
+    AddNode(AddNodeError),
+    /// The component refers to something that isn't part of it, like an enum outside of it
+    Load(LoadError),
+}
+
+impl Template {
+    /// Adds a copy of every node in `component` to a new group named `name` below the node at `parent`
+    ///
+    /// Plain references within the component are made to refer to the nodes of the new instance, so each instance
+    /// works out its values from its own leaves and can be changed without affecting the others. Expressions shared
+    /// by every instance can use path references, like `$^.^.abilities.strength` or `$proficiency`, which are looked
+    /// up from wherever the instance ends up
+    ///
+    /// Nothing is added if the component can't be instantiated
+    pub fn add_instance(&mut self, parent: &str, name: &str, component: &Template) -> Result<GroupHandle<'_>, InstanceError> {
+        let parent_id = match parent {
+            "" => Some(0),
+            path => self.get_node_from(path, 0),
+        }.ok_or_else(|| InstanceError::ParentNotFound(parent.to_owned()))?;
+
+        // Instances added to a `Common` metanode go in its inner group, like any other group
+        let parent_id = match self.get_meta_by_id(parent_id) {
+            Some(Meta { data: Metadata::Common { inner, .. }, .. }) => *inner,
+            _ => parent_id,
+        };
+
+        let source = component.save();
+        let id = self.single_edit(|template| {
+            let id = template.add_group_to(name, parent_id).map_err(InstanceError::AddNode)?.id;
+            template.load_into(&source, id).map_err(InstanceError::Load)?;
+
+            Ok(id)
+        })?;
+
+        Ok(GroupHandle { id, template: self })
+    }
+}
@@ -0,0 +1,370 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{Template, NodeId, Node, Expr, Value, Meta, Metadata};
+
+/// The nodes watching paths from one node by the first name in each path, along with the whole path
+type PathsFrom = HashMap<String, Vec<(NodeId, Vec<String>)>>;
+
+/// Paths that nodes find their dependencies by, kept by the node each path starts from
+///
+/// A node referring to `^.equipment.*.weight` is kept under its parent and `equipment`, so only nodes added, removed or
+/// renamed where they could match the path have to be looked at again
+#[derive(Clone, Debug, Default)]
+pub(super) struct Watches {
+    /// The paths starting from each node
+    by_base: HashMap<NodeId, PathsFrom>,
+    /// The nodes each watching node's paths start from
+    bases: HashMap<NodeId, Vec<NodeId>>,
+    /// Nodes that refer to a path held in another node, which isn't known until they're evaluated
+    unresolved: HashSet<NodeId>,
+}
+
+impl Template {
+    /// Gets the nodes that directly refer to `id`
+    pub fn dependents_of(&self, id: NodeId) -> &[NodeId] {
+        match self.nodes.get(&id).map(|(node, _)| node) {
+            Some(Node::Leaf(leaf)) => &leaf.dependents,
+            Some(Node::Meta(meta)) => &meta.dependents,
+            _ => &[],
+        }
+    }
+
+    /// Gets the nodes that `id` directly refers to
+    pub fn dependencies_of(&self, id: NodeId) -> &[NodeId] {
+        match self.nodes.get(&id).map(|(node, _)| node) {
+            Some(Node::Leaf(leaf)) => &leaf.dependencies,
+            Some(Node::Meta(meta)) => &meta.dependencies,
+            _ => &[],
+        }
+    }
+
+    fn links_mut(&mut self, id: NodeId) -> Option<(&mut Vec<NodeId>, &mut Vec<NodeId>)> {
+        match self.node_mut(id).map(|(node, _)| node) {
+            Some(Node::Leaf(leaf)) => Some((&mut leaf.dependencies, &mut leaf.dependents)),
+            Some(Node::Meta(meta)) => Some((&mut meta.dependencies, &mut meta.dependents)),
+            _ => None,
+        }
+    }
+
+    /// Works out what a node depends on from its expressions
+    fn find_dependencies(&self, id: NodeId) -> Vec<NodeId> {
+        let mut out = Vec::new();
+
+        let mut paths = Vec::new();
+        for expr in self.exprs_of(id) {
+            collect_references(expr, &mut out);
+            collect_paths(expr, &mut paths, &mut false);
+        }
+
+        // Whatever the paths find now, found again whenever a node they could match is added, removed or renamed
+        for path in paths {
+            for found in self.query_from(&path, id) {
+                if found != id && !out.contains(&found) && !matches!(self.nodes.get(&found), Some((Node::Group(_), _))) {
+                    out.push(found);
+                }
+            }
+        }
+
+        // Modifiers and defaults change the value of the leaf they're attached to
+        if let Some(leaf) = self.get_leaf_by_id(id) {
+            out.extend(leaf.metadata.iter().copied().filter(|meta| {
+                matches!(self.get_meta_by_id(*meta), Some(Meta { data: Metadata::Modifier(_) | Metadata::Default(_), .. }))
+            }));
+        }
+
+        out
+    }
+
+    /// Recalculates the dependencies of a node after its expressions have changed
+    /// 
+    /// Only nodes whose links actually change are touched, so they're all the edit has to remember
+    pub(super) fn refresh_dependencies(&mut self, id: NodeId) {
+        self.watch(id);
+
+        let new = self.find_dependencies(id);
+        let old = self.dependencies_of(id).to_vec();
+
+        if old == new {
+            return;
+        }
+
+        let Some((dependencies, _)) = self.links_mut(id) else {
+            return;
+        };
+        *dependencies = new.clone();
+
+        for dependency in old.into_iter().filter(|old| !new.contains(old)) {
+            if self.dependents_of(dependency).contains(&id) {
+                if let Some((_, dependents)) = self.links_mut(dependency) {
+                    dependents.retain(|dependent| *dependent != id);
+                }
+            }
+        }
+
+        for dependency in new {
+            if !self.dependents_of(dependency).contains(&id) {
+                if let Some((_, dependents)) = self.links_mut(dependency) {
+                    dependents.push(id);
+                }
+            }
+        }
+    }
+
+    /// Recalculates every dependency in the template, this is needed after structural edits
+    pub(super) fn refresh_all_dependencies(&mut self) {
+        self.watch_all();
+
+        let all: Vec<(NodeId, Vec<NodeId>)> = self.nodes.iter()
+            .filter(|(_, (node, _))| !matches!(node, Node::Group(_)))
+            .map(|(id, _)| (*id, self.find_dependencies(*id)))
+            .collect();
+
+        let mut dependents: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        for (id, dependencies) in &all {
+            for dependency in dependencies {
+                dependents.entry(*dependency).or_default().push(*id);
+            }
+        }
+
+        for (id, dependencies) in all {
+            let new_dependents = dependents.remove(&id).unwrap_or_default();
+            let unchanged = self.dependencies_of(id) == dependencies
+                && same_nodes(self.dependents_of(id), &new_dependents);
+
+            if !unchanged {
+                if let Some((old_dependencies, old_dependents)) = self.links_mut(id) {
+                    *old_dependencies = dependencies;
+                    *old_dependents = new_dependents;
+                }
+            }
+        }
+    }
+
+    /// Keeps track of the paths a node finds its dependencies by, replacing any it had before
+    pub(super) fn watch(&mut self, id: NodeId) {
+        self.unwatch(id);
+
+        let mut paths = Vec::new();
+        let mut unresolved = false;
+        for expr in self.exprs_of(id) {
+            collect_paths(expr, &mut paths, &mut unresolved);
+        }
+
+        if unresolved {
+            self.watches.unresolved.insert(id);
+        }
+
+        for path in paths {
+            let Some((base, rest)) = self.split_relative(&path, id) else {
+                continue;
+            };
+            let segments: Vec<String> = rest.map(|rest| rest.split('.').map(str::to_owned).collect()).unwrap_or_default();
+            let first = segments.first().cloned().unwrap_or_default();
+
+            self.watches.by_base.entry(base).or_default().entry(first).or_default().push((id, segments));
+            self.watches.bases.entry(id).or_default().push(base);
+        }
+    }
+
+    /// Forgets the paths a node finds its dependencies by
+    pub(super) fn unwatch(&mut self, id: NodeId) {
+        self.watches.unresolved.remove(&id);
+
+        for base in self.watches.bases.remove(&id).unwrap_or_default() {
+            if let Some(by_first) = self.watches.by_base.get_mut(&base) {
+                for watchers in by_first.values_mut() {
+                    watchers.retain(|(watcher, _)| *watcher != id);
+                }
+                by_first.retain(|_, watchers| !watchers.is_empty());
+
+                if by_first.is_empty() {
+                    self.watches.by_base.remove(&base);
+                }
+            }
+        }
+    }
+
+    /// Finds the paths of every node again, after the nodes were replaced all at once
+    pub(super) fn watch_all(&mut self) {
+        *self.watches = Watches::default();
+
+        let ids: Vec<NodeId> = self.nodes.keys().copied().collect();
+        for id in ids {
+            self.watch(id);
+        }
+    }
+
+    /// Gets the nodes with a path that could find `id`, or something inside it
+    pub(super) fn watchers_of(&self, id: NodeId) -> Vec<NodeId> {
+        let mut out = Vec::new();
+        let mut names = Vec::new();
+
+        // The inner group of a `__common` metanode is reached through the metanode itself
+        let mut current = match self.nodes.get(&id) {
+            Some((Node::Group(_), name)) if name == "[COMMON INNER]" => self.common_meta_of(id),
+            _ => Some(id),
+        };
+
+        while let Some(node) = current {
+            if let Some(by_first) = self.watches.by_base.get(&node) {
+                let path: Vec<&str> = names.iter().rev().copied().collect();
+                let watches: Vec<_> = match path.first() {
+                    Some(first) => [*first, "*", "**"].into_iter().filter_map(|key| by_first.get(key)).flatten().collect(),
+                    None => by_first.values().flatten().collect(),
+                };
+
+                for (watcher, segments) in watches {
+                    if !out.contains(watcher) && reaches(segments, &path) {
+                        out.push(*watcher);
+                    }
+                }
+            }
+
+            if let Some((_, name)) = self.nodes.get(&node) {
+                names.push(name.as_str());
+            }
+            current = self.parent_of(node);
+        }
+
+        out
+    }
+
+    /// Finds again what the nodes watching `id` refer to, after it was added or renamed
+    pub(super) fn refresh_watchers_of(&mut self, id: NodeId) {
+        let watchers = self.watchers_of(id);
+        self.refresh_watchers(watchers);
+    }
+
+    /// Finds again what some nodes refer to and marks their values as stale
+    pub(super) fn refresh_watchers(&mut self, watchers: Vec<NodeId>) {
+        for watcher in watchers {
+            if self.nodes.contains_key(&watcher) {
+                self.refresh_dependencies(watcher);
+                self.invalidate_dependents(watcher);
+            }
+        }
+    }
+
+    /// Takes a node out of the dependents of everything it depends on and stops watching its paths, before it's removed
+    pub(super) fn unlink(&mut self, id: NodeId) {
+        for dependency in self.dependencies_of(id).to_vec() {
+            if self.dependents_of(dependency).contains(&id) {
+                if let Some((_, dependents)) = self.links_mut(dependency) {
+                    dependents.retain(|dependent| *dependent != id);
+                }
+            }
+        }
+
+        self.unwatch(id);
+    }
+
+    /// Marks any identifiers among `ids` as stale, after the names or positions they describe changed
+    pub(super) fn invalidate_identifiers(&mut self, ids: Vec<NodeId>) {
+        for id in ids {
+            if let Some(Meta { data: Metadata::Ident(_), .. }) = self.get_meta_by_id(id) {
+                self.invalidate_dependents(id);
+            }
+        }
+    }
+
+    /// Marks the cache of a node and everything that depends on it as stale
+    /// 
+    /// During a transaction this is put off until the transaction is finished
+    pub(super) fn invalidate_dependents(&mut self, id: NodeId) {
+        match &mut self.pending_invalidation {
+            Some(pending) => pending.push(id),
+            None => self.invalidate_all_dependents(vec![id]),
+        }
+    }
+
+    /// Marks the caches of several nodes and everything that depends on them as stale, in a single pass
+    pub(super) fn invalidate_all_dependents(&mut self, ids: Vec<NodeId>) {
+        // Nodes referring to paths held in other nodes can't be tracked, so any edit could change them
+        let mut stack = ids;
+        stack.extend(self.watches.unresolved.iter().copied());
+
+        let mut visited = HashSet::new();
+
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+
+            // Caches aren't part of any edit, so this doesn't go through `node_mut`
+            match self.nodes.get_mut(&id).map(|(node, _)| node) {
+                Some(Node::Leaf(leaf)) => {
+                    leaf.cache_valid = false;
+                    stack.extend(leaf.dependents.iter().copied());
+                },
+                Some(Node::Meta(meta)) => {
+                    meta.cache_valid = false;
+                    stack.extend(meta.dependents.iter().copied());
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Finds the nodes referenced directly by ID within an expression
+fn collect_references(expr: &Expr, out: &mut Vec<NodeId>) {
+    match expr {
+        Expr::Reference(id) | Expr::IdentRef(id) if !out.contains(id) => out.push(*id),
+        Expr::InfixOp(op) => {
+            collect_references(&op.lhs, out);
+            collect_references(&op.rhs, out);
+        },
+        Expr::Literal(Value::List(items)) => {
+            for item in items {
+                collect_references(item, out);
+            }
+        },
+        Expr::Lookup(lookup) => collect_references(&lookup.key, out),
+        Expr::Has(inner) => collect_references(inner, out),
+        _ => (),
+    }
+}
+
+/// Whether two lists have the same nodes, in any order
+fn same_nodes(a: &[NodeId], b: &[NodeId]) -> bool {
+    a.len() == b.len() && a.iter().all(|id| b.contains(id))
+}
+
+/// Finds the paths and patterns an expression finds nodes by, and whether it refers to a path held in another node
+fn collect_paths(expr: &Expr, out: &mut Vec<String>, unresolved: &mut bool) {
+    match expr {
+        Expr::PathRef(path) => out.push(path.clone()),
+        Expr::Aggregate(aggregate) => out.push(aggregate.pattern.clone()),
+        Expr::Lookup(lookup) => {
+            out.push(lookup.table.clone());
+            collect_paths(&lookup.key, out, unresolved);
+        },
+        Expr::IdentRef(_) => *unresolved = true,
+        Expr::InfixOp(op) => {
+            collect_paths(&op.lhs, out, unresolved);
+            collect_paths(&op.rhs, out, unresolved);
+        },
+        Expr::Literal(Value::List(items)) => {
+            for item in items {
+                collect_paths(item, out, unresolved);
+            }
+        },
+        Expr::Has(inner) => collect_paths(inner, out, unresolved),
+        _ => (),
+    }
+}
+
+/// Whether the names leading from where a path starts to a node could be the start of what the path matches
+///
+/// `*` matches any one name and `**` any number of them
+fn reaches(segments: &[String], names: &[&str]) -> bool {
+    let Some((name, rest_names)) = names.split_first() else {
+        return true;
+    };
+
+    match segments.split_first() {
+        None => false,
+        Some((segment, rest)) if segment == "**" => reaches(rest, names) || reaches(segments, rest_names),
+        Some((segment, rest)) => (segment == "*" || segment == name) && reaches(rest, rest_names),
+    }
+}
@@ -0,0 +1,222 @@
+use std::{collections::{HashMap, HashSet}, fmt};
+
+use super::{LoadError, LoadErrorKind, Template};
+
+/// A difference between two templates, by path
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// The node only exists in the new template
+    Added(String),
+    /// The node only exists in the old template
+    Removed(String),
+    /// The node and everything in it were moved to a new name in the same parent
+    Renamed { from: String, to: String },
+    /// The node's value, expression or other contents changed, shown as lines of the format read by [`Template::load`]
+    Changed { path: String, before: String, after: String },
+}
+
+/// A node both sides of a merge changed in different ways, found by [`Template::merge`] and [`Template::rebase`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// Both added a different node with the same path
+    BothAdded(String),
+    /// Both changed the node in different ways
+    BothChanged(String),
+    /// This template changed a node the other removed
+    ChangedRemoved(String),
+    /// This template removed a node the other changed
+    RemovedChanged(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MergeError {
+    Conflicts(Vec<Conflict>),
+    /// The changes don't make sense together, like a node added to a group the other side removed
+    Invalid(LoadError),
+}
+
+impl Template {
+    /// Gets every difference between this template and `other`, parents first
+    ///
+    /// Nodes are matched up by path, so a node moved to a new name shows up as `Renamed` only if nothing inside it
+    /// changed too, including references between the nodes inside it. Anything referring to a renamed node shows up as `Changed`, since it refers to the new path
+    pub fn diff(&self, other: &Template) -> Vec<Change> {
+        let old_lines = self.save_lines();
+        let new_lines = other.save_lines();
+        let old_by_path: HashMap<_, _> = old_lines.iter().map(|(path, line)| (path.as_str(), line)).collect();
+        let new_by_path: HashMap<_, _> = new_lines.iter().map(|(path, line)| (path.as_str(), line)).collect();
+
+        let removed: Vec<_> = old_lines.iter().map(|(path, _)| path.as_str()).filter(|path| !new_by_path.contains_key(path)).collect();
+        let added: Vec<_> = new_lines.iter().map(|(path, _)| path.as_str()).filter(|path| !old_by_path.contains_key(path)).collect();
+
+        // A removed node matches an added node in the same parent if everything inside them is the same
+        let mut renames = Vec::new();
+        for from in removed.iter().filter(|path| !removed.contains(&parent_path(path))) {
+            let signature = subtree_signature(&old_lines, from);
+            let found = added.iter()
+                .filter(|to| !added.contains(&parent_path(to)) && parent_path(to) == parent_path(from))
+                .find(|to| !renames.iter().any(|(_, renamed)| renamed == *to) && subtree_signature(&new_lines, to) == signature);
+
+            if let Some(to) = found {
+                renames.push((*from, *to));
+            }
+        }
+        let renamed_from: HashSet<_> = renames.iter().map(|(from, _)| *from).collect();
+        let renamed_to: HashSet<_> = renames.iter().map(|(_, to)| *to).collect();
+        let renamed = |path: &str, roots: &HashSet<&str>| roots.iter().any(|root| is_within(path, root));
+
+        let mut changes: Vec<_> = old_lines.iter().filter_map(|(path, before)| match new_by_path.get(path.as_str()) {
+            None if renamed_from.contains(path.as_str()) => {
+                let to = renames.iter().find(|(from, _)| from == path).map(|(_, to)| to.to_string())?;
+
+                Some(Change::Renamed { from: path.clone(), to })
+            },
+            None if renamed(path, &renamed_from) => None,
+            None => Some(Change::Removed(path.clone())),
+            Some(after) if *after != before => Some(Change::Changed { path: path.clone(), before: before.clone(), after: (*after).clone() }),
+            Some(_) => None,
+        }).collect();
+
+        changes.extend(added.iter().filter(|path| !renamed(path, &renamed_to)).map(|path| Change::Added(path.to_string())));
+
+        changes
+    }
+
+    /// Applies the changes made between `base` and `theirs` to this template, where this template was also based on
+    /// `base`
+    ///
+    /// Changes only made on one side are kept, and nodes changed on both sides must have been changed the same way.
+    /// Nothing is changed if there are any conflicts. The merge is a single edit, so it can be undone
+    ///
+    /// Nodes are added, removed and changed in place, so every node still in the template keeps its ID. A node whose
+    /// kind changed is removed and added again, and new nodes go after the nodes already in their parent
+    pub fn merge(&mut self, base: &Template, theirs: &Template) -> Result<(), MergeError> {
+        let merged = merge_lines(base, self, theirs)?;
+        let our_lines = self.save_lines();
+        let ours: HashMap<_, _> = our_lines.iter().cloned().collect();
+        let merged_by_path: HashMap<_, _> = merged.iter().map(|(path, line)| (path.as_str(), line.as_str())).collect();
+
+        // A node has to be made again if it isn't the same kind of node any more
+        let kind = |line: &str| line.split_whitespace().next().map(str::to_owned);
+        let remade: HashSet<&str> = merged.iter()
+            .filter(|(path, line)| ours.get(path).is_some_and(|our_line| kind(our_line) != kind(line)))
+            .map(|(path, _)| path.as_str())
+            .collect();
+        let removed: Vec<&String> = our_lines.iter()
+            .map(|(path, _)| path)
+            .filter(|path| !merged_by_path.contains_key(path.as_str()) || remade.contains(path.as_str()))
+            .collect();
+        let error = |index: usize| move |kind: LoadErrorKind| MergeError::Invalid(LoadError { line: index + 1, kind });
+
+        self.single_edit(|template| {
+            // Removing a node removes everything inside it, so anything already gone is skipped
+            for path in removed {
+                if let Some(id) = template.resolve_path(path, 0) {
+                    template.remove_node(id).expect("the node isn't the root");
+                }
+            }
+
+            // Like loading, every node is created before anything is filled in so nodes can refer to ones further down
+            for (index, (path, line)) in merged.iter().enumerate() {
+                if !ours.contains_key(path) || remade.contains(path.as_str()) {
+                    template.load_node(line, 0).map_err(error(index))?;
+                }
+            }
+
+            for leaves in [false, true] {
+                for (index, (path, line)) in merged.iter().enumerate() {
+                    if ours.get(path) == Some(line) || (kind(line).as_deref() == Some("leaf")) != leaves {
+                        continue;
+                    }
+
+                    // A changed leaf is filled in from scratch, the same as a new one
+                    if let Some(id) = template.resolve_path(path, 0).filter(|id| template.get_leaf_by_id(*id).is_some()) {
+                        template.reset_leaf(id);
+                    }
+                    template.load_declaration(line, 0).map_err(error(index))?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(path) => write!(f, "added {path}"),
+            Change::Removed(path) => write!(f, "removed {path}"),
+            Change::Renamed { from, to } => write!(f, "renamed {from} to {to}"),
+            Change::Changed { path, before, after } => write!(f, "changed {path}\n  - {before}\n  + {after}"),
+        }
+    }
+}
+
+/// Merges the lines of two templates based on the same template, giving the path and line of every merged node with
+/// parents first
+fn merge_lines(base: &Template, ours: &Template, theirs: &Template) -> Result<Vec<(String, String)>, MergeError> {
+    let base_lines: HashMap<_, _> = base.save_lines().into_iter().collect();
+    let our_lines = ours.save_lines();
+    let their_lines = theirs.save_lines();
+    let our_by_path: HashMap<_, _> = our_lines.iter().cloned().collect();
+    let their_by_path: HashMap<_, _> = their_lines.iter().cloned().collect();
+
+    // Their order is kept, with anything only we have after it
+    let paths = their_lines.iter().chain(our_lines.iter().filter(|(path, _)| !their_by_path.contains_key(path)));
+    let mut merged = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for (path, _) in paths {
+        let (base, ours, theirs) = (base_lines.get(path), our_by_path.get(path), their_by_path.get(path));
+
+        let line = if ours == theirs || theirs == base {
+            ours
+        } else if ours == base {
+            theirs
+        } else {
+            conflicts.push(match (base, ours, theirs) {
+                (None, _, _) => Conflict::BothAdded(path.clone()),
+                (_, None, _) => Conflict::RemovedChanged(path.clone()),
+                (_, _, None) => Conflict::ChangedRemoved(path.clone()),
+                _ => Conflict::BothChanged(path.clone()),
+            });
+
+            continue;
+        };
+
+        if let Some(line) = line {
+            merged.push((path.clone(), line.clone()));
+        }
+    }
+
+    if !conflicts.is_empty() {
+        return Err(MergeError::Conflicts(conflicts));
+    }
+
+    // Parents have to come before their children, which might not be the case if each side added one of them
+    merged.sort_by_key(|(path, _)| path.split('.').count());
+
+    Ok(merged)
+}
+
+/// Gets the path of the parent of the node at `path`, which is `""` for nodes in the root
+fn parent_path(path: &str) -> &str {
+    path.rsplit_once('.').map(|(parent, _)| parent).unwrap_or("")
+}
+
+/// Whether the node at `path` is the node at `root` or inside it
+fn is_within(path: &str, root: &str) -> bool {
+    path == root || path.strip_prefix(root).is_some_and(|rest| rest.starts_with('.'))
+}
+
+/// Describes a node and everything inside it without its own path, so it can be compared with the same nodes elsewhere
+fn subtree_signature(lines: &[(String, String)], root: &str) -> Vec<(String, String)> {
+    let mut signature: Vec<_> = lines.iter()
+        .filter(|(path, _)| is_within(path, root))
+        .map(|(path, line)| (path[root.len()..].to_owned(), Template::line_without_path(line, path)))
+        .collect();
+    signature.sort();
+
+    signature
+}
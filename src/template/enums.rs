@@ -0,0 +1,78 @@
+use super::{Template, NodeId, Node, Expr, Value, ValueKind, Meta, Metadata, EditLeafError};
+
+impl Template {
+    /// Gets the variants of the `Enum` metanode `id`
+    pub fn enum_variants(&self, id: NodeId) -> Option<&[String]> {
+        match self.get_meta_by_id(id)? {
+            Meta { data: Metadata::Enum(variants), .. } => Some(variants),
+            _ => None,
+        }
+    }
+
+    /// Gets the valid values for a leaf if it holds an enum, such as for a dropdown
+    pub fn options(&self, id: NodeId) -> Option<&[String]> {
+        match self.get_leaf_by_id(id)?.value_kind {
+            ValueKind::Enum(enum_id) => self.enum_variants(enum_id),
+            _ => None,
+        }
+    }
+
+    /// Sets the kind of value a leaf holds
+    /// 
+    /// This is mostly useful for making a leaf hold an enum, so strings given to it later are checked against the
+    /// variants. The current value of the leaf must be valid for the new kind
+    pub(super) fn set_leaf_kind(&mut self, id: NodeId, kind: ValueKind) -> Result<(), EditLeafError> {
+        self.single_edit(|template| template.change_leaf_kind(id, kind))
+    }
+
+    fn change_leaf_kind(&mut self, id: NodeId, kind: ValueKind) -> Result<(), EditLeafError> {
+        if let ValueKind::Enum(enum_id) = kind {
+            self.enum_variants(enum_id).ok_or(EditLeafError::NotEnum)?;
+        }
+
+        let leaf = match self.nodes.get(&id).ok_or(EditLeafError::NotExists)? {
+            (Node::Leaf(leaf), _) => leaf,
+            _ => return Err(EditLeafError::NotLeaf),
+        };
+
+        let old_kind = leaf.value_kind;
+        let value = leaf.value.clone();
+
+        self.get_mut_leaf_by_id(id).ok_or(EditLeafError::NotLeaf)?.value_kind = kind;
+
+        // Literal values are checked again so strings become enum values
+        if let Some(Expr::Literal(value)) = value {
+            if let Err(err) = self.set_leaf_value(id, value) {
+                self.get_mut_leaf_by_id(id).ok_or(EditLeafError::NotLeaf)?.value_kind = old_kind;
+
+                return Err(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure a value can be stored in a leaf, converting strings to enum values if the leaf holds an enum
+    pub(super) fn check_enum_value(&self, id: NodeId, value: Value) -> Result<Value, EditLeafError> {
+        let leaf = match self.nodes.get(&id).ok_or(EditLeafError::NotExists)? {
+            (Node::Leaf(leaf), _) => leaf,
+            _ => return Err(EditLeafError::NotLeaf),
+        };
+
+        let (enum_id, variant) = match (leaf.value_kind, &value) {
+            (ValueKind::Enum(enum_id), Value::Enum { id: value_id, variant }) if enum_id == *value_id => (enum_id, variant),
+            (ValueKind::Enum(enum_id), Value::String(variant)) => (enum_id, variant),
+            (ValueKind::Enum(_), _) => return Err(EditLeafError::InvalidVariant),
+            (_, Value::Enum { id: enum_id, variant }) => (*enum_id, variant),
+            _ => return Ok(value),
+        };
+
+        let variants = self.enum_variants(enum_id).ok_or(EditLeafError::NotEnum)?;
+
+        if variants.contains(variant) {
+            Ok(Value::Enum { id: enum_id, variant: variant.clone() })
+        } else {
+            Err(EditLeafError::InvalidVariant)
+        }
+    }
+}
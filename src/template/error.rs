@@ -0,0 +1,388 @@
+use std::{error::Error, fmt};
+
+use super::{AddNodeError, EditLeafError, EvalCache, EvalError, NodeId, RemoveNodeError, Template, Value};
+use super::meta::{EditMetaError, PushCommonError};
+use super::{ParseError, ParseErrorKind, LoadError, LoadErrorKind, Conflict, RebaseError, InstanceError, CollectionError, TransactionError, MergeError};
+
+/// An error together with where in the template it happened
+/// 
+/// This is how errors should be shown to users, since it's displayed with the paths the error on its own doesn't know,
+/// like `half: division by zero (while evaluating total -> half)`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ErrorReport<E> {
+    pub error: E,
+    /// Dotted path of the node the error is about, if it can still be found
+    pub path: Option<String>,
+    /// Dotted paths of the nodes that were being evaluated when the error happened, outermost first
+    pub chain: Vec<String>,
+}
+
+impl EvalError {
+    /// The node the error is about, if it names one
+    pub fn node(&self) -> Option<NodeId> {
+        match self {
+            EvalError::NotALeaf(id)
+            | EvalError::InfiniteRecursion(id)
+            | EvalError::MissingInfo(id)
+            | EvalError::MissingDependency(id)
+            | EvalError::InvalidIdentRef(id)
+            | EvalError::MetaType(id)
+            | EvalError::MissingParent(id)
+            | EvalError::ConcatElement { id, .. }
+            | EvalError::MissingOptional(id)
+            | EvalError::NotATable(id)
+            | EvalError::KeyNotInTable { id, .. }
+            | EvalError::ConstraintViolated { id, .. }
+            | EvalError::InvalidVariant { id, .. } => Some(*id),
+            EvalError::MissingPathDependency(_) | EvalError::InvalidType | EvalError::DivisionByZero
+            | EvalError::NegativeExponent | EvalError::Overflow => None,
+        }
+    }
+}
+
+impl Template {
+    /// Evaluates a leaf like [`Template::eval_leaf`], but describes any error with paths instead of IDs
+    pub fn eval_leaf_report(&mut self, id: NodeId) -> Result<Value, ErrorReport<EvalError>> {
+        let mut cache = EvalCache::new();
+
+        match self.eval_leaf_cached(id, &mut cache) {
+            Ok(out) => {
+                self.store_cache(cache);
+
+                Ok(out)
+            },
+            Err(error) => Err(self.report_eval(error, cache.failure.as_deref().unwrap_or(&[id]))),
+        }
+    }
+
+    /// Describes an evaluation error, given the IDs of the nodes being evaluated when it happened
+    pub fn report_eval(&self, error: EvalError, chain: &[NodeId]) -> ErrorReport<EvalError> {
+        // Errors about paths that don't resolve are reported at the node containing the path
+        let subject = error.node()
+            .filter(|id| self.nodes.contains_key(id))
+            .or_else(|| chain.last().copied());
+
+        ErrorReport {
+            path: subject.and_then(|id| self.path_of(id)),
+            chain: chain.iter().map(|id| self.path_of(*id).unwrap_or_else(|| format!("#{id}"))).collect(),
+            error,
+        }
+    }
+
+    /// Describes an error from adding a node named `name` to `parent`
+    pub fn report_add(&self, error: AddNodeError, parent: NodeId, name: &str) -> ErrorReport<AddNodeError> {
+        let path = match self.path_of(parent) {
+            Some(parent) if parent.is_empty() => Some(name.to_owned()),
+            Some(parent) => Some(format!("{parent}.{name}")),
+            None => None,
+        };
+
+        ErrorReport { error, path, chain: Vec::new() }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for ErrorReport<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The path already names the node, so errors which can leave it out do
+        match &self.path {
+            Some(path) => write!(f, "{path}: {:#}", self.error)?,
+            None => write!(f, "{}", self.error)?,
+        }
+
+        if self.chain.len() > 1 {
+            write!(f, " (while evaluating {})", self.chain.join(" -> "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<E: Error + 'static> Error for ErrorReport<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl fmt::Display for EvalError {
+    /// The alternate form `{:#}` leaves out the node the error is about, for when it's already named by path like in an
+    /// [`ErrorReport`]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            match self {
+                EvalError::NotALeaf(_) => return write!(f, "is a group and has no value"),
+                EvalError::InfiniteRecursion(_) => return write!(f, "depends on itself"),
+                EvalError::MissingInfo(_) => return write!(f, "has no value"),
+                EvalError::InvalidIdentRef(_) => return write!(f, "doesn't evaluate to the name of a node"),
+                EvalError::MetaType(_) => return write!(f, "can't produce a value of that type"),
+                EvalError::MissingParent(_) => return write!(f, "has no parent"),
+                EvalError::ConcatElement { index, .. } => return write!(f, "element {index} couldn't be turned into a string"),
+                EvalError::MissingOptional(_) => return write!(f, "is optional and has no value"),
+                EvalError::NotATable(_) => return write!(f, "isn't a table"),
+                EvalError::KeyNotInTable { key, .. } => return write!(f, "no row matches {key}"),
+                EvalError::ConstraintViolated { value, .. } => return write!(f, "{value} doesn't satisfy the constraint"),
+                EvalError::InvalidVariant { variant, .. } => return write!(f, "`{variant}` isn't one of the variants"),
+                // The node doesn't exist, so the report names the node referring to it instead
+                _ => (),
+            }
+        }
+
+        match self {
+            EvalError::NotALeaf(id) => write!(f, "node #{id} is a group and has no value"),
+            EvalError::InfiniteRecursion(id) => write!(f, "node #{id} depends on itself"),
+            EvalError::MissingInfo(id) => write!(f, "node #{id} has no value"),
+            EvalError::MissingDependency(id) => write!(f, "node #{id} doesn't exist"),
+            EvalError::MissingPathDependency(path) => write!(f, "no node found at `{path}`"),
+            EvalError::InvalidIdentRef(id) => write!(f, "node #{id} doesn't evaluate to the name of a node"),
+            EvalError::InvalidType => write!(f, "a value has the wrong type"),
+            EvalError::MetaType(id) => write!(f, "metanode #{id} can't produce a value of that type"),
+            EvalError::MissingParent(id) => write!(f, "node #{id} has no parent"),
+            EvalError::ConcatElement { id, index, .. } => write!(f, "element {index} of concat #{id} couldn't be turned into a string"),
+            EvalError::MissingOptional(id) => write!(f, "optional node #{id} has no value"),
+            EvalError::NotATable(id) => write!(f, "node #{id} isn't a table"),
+            EvalError::KeyNotInTable { id, key } => write!(f, "no row of table #{id} matches {key}"),
+            EvalError::ConstraintViolated { id, value } => write!(f, "{value} doesn't satisfy constraint #{id}"),
+            EvalError::InvalidVariant { id, variant } => write!(f, "`{variant}` isn't a variant of enum #{id}"),
+            EvalError::DivisionByZero => write!(f, "division by zero"),
+            EvalError::NegativeExponent => write!(f, "negative exponent"),
+            EvalError::Overflow => write!(f, "an integer is too large"),
+        }
+    }
+}
+
+impl Error for EvalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EvalError::ConcatElement { cause, .. } => Some(cause.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AddNodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddNodeError::ParentNotExists => write!(f, "the parent doesn't exist"),
+            AddNodeError::ParentIsLeaf => write!(f, "the parent is a leaf and can't have children"),
+            AddNodeError::InvalidParent => write!(f, "the parent can't hold this kind of node"),
+            AddNodeError::NameConflict => write!(f, "a node with this name already exists"),
+            AddNodeError::InvalidName => write!(f, "the name is invalid"),
+        }
+    }
+}
+
+impl Error for AddNodeError {}
+
+impl fmt::Display for RemoveNodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RemoveNodeError::NotExists => write!(f, "the node doesn't exist"),
+            RemoveNodeError::IsRoot => write!(f, "the root node can't be removed"),
+        }
+    }
+}
+
+impl Error for RemoveNodeError {}
+
+impl fmt::Display for EditLeafError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditLeafError::NotExists => write!(f, "the leaf doesn't exist"),
+            EditLeafError::NotLeaf => write!(f, "the node isn't a leaf"),
+            EditLeafError::InvalidVariant => write!(f, "the value isn't a variant of the leaf's enum"),
+            EditLeafError::NotEnum => write!(f, "the kind refers to a node that isn't an enum"),
+        }
+    }
+}
+
+impl Error for EditLeafError {}
+
+impl fmt::Display for EditMetaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditMetaError::WrongKind => write!(f, "the metanode is a different kind"),
+        }
+    }
+}
+
+impl Error for EditMetaError {}
+
+impl fmt::Display for PushCommonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PushCommonError::CommonNotExists => write!(f, "the common metanode doesn't exist"),
+            PushCommonError::ParentNotGroup => write!(f, "the common metanode's parent isn't a group"),
+            PushCommonError::NotCommon => write!(f, "the metanode isn't a common metanode"),
+        }
+    }
+}
+
+impl Error for PushCommonError {}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ParseErrorKind::UnexpectedEnd => write!(f, "unexpected end of expression"),
+            ParseErrorKind::UnexpectedChar(c) => write!(f, "unexpected `{c}` at {}", self.position),
+            ParseErrorKind::InvalidInteger => write!(f, "integer at {} is too large", self.position),
+            ParseErrorKind::UnknownPath(path) => write!(f, "no node found at `{path}` (at {})", self.position),
+            ParseErrorKind::TrailingInput => write!(f, "unexpected input after the expression at {}", self.position),
+            ParseErrorKind::UnexpectedWord(word) => write!(f, "unexpected `{word}` at {}", self.position),
+            ParseErrorKind::NotLiteral => write!(f, "expected a literal value at {}", self.position),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+
+        match &self.kind {
+            LoadErrorKind::Parse(err) => write!(f, "{err}"),
+            LoadErrorKind::AddNode(err) => write!(f, "{err}"),
+            LoadErrorKind::EditLeaf(err) => write!(f, "{err}"),
+            LoadErrorKind::EditMeta(err) => write!(f, "{err}"),
+            LoadErrorKind::UnknownKind(kind) => write!(f, "`{kind}` isn't a kind of node"),
+            LoadErrorKind::Schema(err) => write!(f, "in the collection's schema, {err}"),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            LoadErrorKind::Parse(err) => Some(err),
+            LoadErrorKind::AddNode(err) => Some(err),
+            LoadErrorKind::EditLeaf(err) => Some(err),
+            LoadErrorKind::EditMeta(err) => Some(err),
+            LoadErrorKind::UnknownKind(_) => None,
+            LoadErrorKind::Schema(err) => Some(err.as_ref()),
+        }
+    }
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Conflict::BothAdded(path) => write!(f, "{path}: added differently by both"),
+            Conflict::BothChanged(path) => write!(f, "{path}: changed differently by both"),
+            Conflict::ChangedRemoved(path) => write!(f, "{path}: changed here but removed by the other side"),
+            Conflict::RemovedChanged(path) => write!(f, "{path}: removed here but changed by the other side"),
+        }
+    }
+}
+
+impl fmt::Display for RebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RebaseError::NoBase => write!(f, "the template wasn't derived from another"),
+            RebaseError::Conflicts(conflicts) => {
+                write!(f, "{} conflicting changes", conflicts.len())?;
+
+                for conflict in conflicts {
+                    write!(f, "\n  {conflict}")?;
+                }
+
+                Ok(())
+            },
+            RebaseError::Invalid(err) => write!(f, "the changes can't be applied to the new base: {err}"),
+        }
+    }
+}
+
+impl Error for RebaseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RebaseError::Invalid(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for InstanceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstanceError::ParentNotFound(path) => write!(f, "no node found at `{path}`"),
+            InstanceError::AddNode(err) => write!(f, "{err}"),
+            InstanceError::Load(err) => write!(f, "the component can't be instantiated: {err}"),
+        }
+    }
+}
+
+impl Error for InstanceError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            InstanceError::ParentNotFound(_) => None,
+            InstanceError::AddNode(err) => Some(err),
+            InstanceError::Load(err) => Some(err),
+        }
+    }
+}
+
+impl fmt::Display for CollectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CollectionError::NotCollection => write!(f, "the node isn't a collection"),
+            CollectionError::KeyNeeded => write!(f, "items in this collection need a key"),
+            CollectionError::NotKeyed => write!(f, "items in this collection are named by position and can't have a key"),
+            CollectionError::OutOfRange(index) => write!(f, "there's no item at {index}"),
+            CollectionError::AddNode(err) => write!(f, "{err}"),
+            CollectionError::Load(err) => write!(f, "the schema can't be instantiated: {err}"),
+        }
+    }
+}
+
+impl Error for CollectionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CollectionError::AddNode(err) => Some(err),
+            CollectionError::Load(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for TransactionError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::Edit(err) => write!(f, "{err}"),
+            TransactionError::Invalid(report) => write!(f, "the edits would leave a problem: {report}"),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for TransactionError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TransactionError::Edit(err) => Some(err),
+            TransactionError::Invalid(report) => Some(report),
+        }
+    }
+}
+
+impl fmt::Display for MergeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MergeError::Conflicts(conflicts) => {
+                write!(f, "{} conflicting changes", conflicts.len())?;
+
+                for conflict in conflicts {
+                    write!(f, "\n  {conflict}")?;
+                }
+
+                Ok(())
+            },
+            MergeError::Invalid(err) => write!(f, "the changes can't be combined: {err}"),
+        }
+    }
+}
+
+impl Error for MergeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MergeError::Invalid(err) => Some(err),
+            MergeError::Conflicts(_) => None,
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::fmt;
+
+use super::{Template, NodeId, Node, Leaf, Meta, Expr, Value, Integer, OpKind, Metadata, EvalError, EvalCache, AggregateKind, ModifierOp};
+
+/// A tree describing how a value was derived, as returned by [`Template::explain`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Explanation {
+    /// What was evaluated at this point
+    pub step: Step,
+    /// The value produced by this step
+    pub value: Value,
+    /// The steps whose values were used to produce this one
+    pub children: Vec<Explanation>,
+}
+
+/// A single step in the derivation of a value
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// The expression stored in a leaf
+    Leaf { id: NodeId, path: String },
+    /// The value of a metanode
+    Meta { id: NodeId, path: String },
+    /// A literal value
+    Literal,
+    /// A modifier applied to the value of a leaf, the value of this step is its operand
+    Modifier { source: String, op: ModifierOp },
+    /// A single named part of a sum
+    Contribution { source: String },
+    /// A direct reference to another node
+    Reference { id: NodeId, path: String },
+    /// A reference through a path as written in the expression, which resolved to node `resolved`
+    PathRef { path: String, resolved: NodeId },
+    /// A reference through the path contained in node `id`, which resolved to node `resolved`
+    IdentRef { id: NodeId, name: String, resolved: NodeId },
+    /// An aggregate over every leaf matching `pattern`, with one child per match
+    Aggregate { kind: AggregateKind, pattern: String },
+    /// A lookup in the table `id` of the key given by the only child
+    Lookup { id: NodeId, path: String },
+    /// A check for whether the only child has a value, which is left out if it doesn't
+    Has,
+    /// An operation on the values of both children, or the one child that was used for `OpKind::Coalesce`
+    InfixOp(OpKind),
+}
+
+impl Template {
+    /// Evaluates the node `id`, recording every step taken to get to its value
+    /// 
+    /// Caches are ignored so the whole derivation is always included
+    pub fn explain(&self, id: NodeId) -> Result<Explanation, EvalError> {
+        self.explain_node(id, &mut Vec::new())
+    }
+
+    fn explain_node(&self, id: NodeId, checked: &mut Vec<NodeId>) -> Result<Explanation, EvalError> {
+        if checked.contains(&id) {
+            return Err(EvalError::InfiniteRecursion(id));
+        }
+
+        let path = self.path_of(id).unwrap_or_default();
+
+        checked.push(id);
+        let out = match &self.nodes.get(&id).ok_or(EvalError::MissingDependency(id))?.0 {
+            Node::Leaf(leaf) => self.explain_leaf(leaf, checked).map(|(value, children)| Explanation {
+                step: Step::Leaf { id, path },
+                value,
+                children,
+            }),
+            Node::Group(_) => Err(EvalError::NotALeaf(id)),
+            Node::Meta(meta) => self.explain_meta(meta, checked).map(|(value, children)| Explanation {
+                step: Step::Meta { id, path },
+                value,
+                children,
+            }),
+        };
+        checked.pop();
+
+        out
+    }
+
+    /// Explains the expression or default of a leaf followed by each modifier applied to it
+    fn explain_leaf(&self, leaf: &Leaf, checked: &mut Vec<NodeId>) -> Result<(Value, Vec<Explanation>), EvalError> {
+        let base = match (&leaf.value, self.default_of(leaf)) {
+            (Some(expr), _) => self.explain_expr(expr, checked)?,
+            (None, Some(default)) => self.explain_node(default, checked)?,
+            (None, None) if leaf.optional => return Err(EvalError::MissingOptional(leaf.id)),
+            (None, None) => return Err(EvalError::MissingInfo(leaf.id)),
+        };
+        let mut value = base.value.clone();
+        let mut children = vec![base];
+
+        for (meta_id, modifier, operand) in self.applicable_modifiers(leaf, checked, &mut EvalCache::new())? {
+            let Value::Integer(current) = value else {
+                return Err(EvalError::InvalidType);
+            };
+            value = Value::Integer(modifier.apply(current, operand)?);
+
+            // The operand is evaluated from the position of the modifier's metanode
+            checked.push(meta_id);
+            let inner = self.explain_expr(&modifier.value, checked);
+            checked.pop();
+
+            children.push(Explanation {
+                step: Step::Modifier { source: modifier.source.clone(), op: modifier.op },
+                value: Value::Integer(operand),
+                children: vec![inner?],
+            });
+        }
+
+        Ok((value, children))
+    }
+
+    /// Explains each part of a metanode, working out its value from the parts so nothing is evaluated twice
+    fn explain_meta(&self, meta: &Meta, checked: &mut Vec<NodeId>) -> Result<(Value, Vec<Explanation>), EvalError> {
+        match &meta.data {
+            Metadata::Sum(contributions) => {
+                let mut sum: Integer = 0;
+                let mut children = Vec::with_capacity(contributions.len());
+
+                for contribution in contributions {
+                    let inner = self.explain_expr(&contribution.expr, checked)?;
+                    let Value::Integer(value) = inner.value else {
+                        return Err(EvalError::InvalidType);
+                    };
+                    sum = sum.checked_add(value).ok_or(EvalError::Overflow)?;
+
+                    children.push(Explanation {
+                        step: Step::Contribution { source: contribution.source.clone() },
+                        value: inner.value.clone(),
+                        children: vec![inner],
+                    });
+                }
+
+                Ok((Value::Integer(sum), children))
+            },
+            Metadata::Default(expr) => {
+                let inner = self.explain_expr(expr, checked)?;
+
+                Ok((inner.value.clone(), vec![inner]))
+            },
+            Metadata::Concat(elements) => {
+                let mut out = String::new();
+                let mut children = Vec::with_capacity(elements.len());
+
+                for (index, element) in elements.iter().enumerate() {
+                    let failed = |cause| EvalError::ConcatElement { id: meta.id, index, cause: Box::new(cause) };
+                    let inner = self.explain_expr(&element.expr, checked).map_err(failed)?;
+
+                    match &inner.value {
+                        Value::String(value) => out.push_str(value),
+                        Value::Integer(value) => out.push_str(&element.format.apply(*value)),
+                        _ => return Err(failed(EvalError::InvalidType)),
+                    }
+                    children.push(inner);
+                }
+
+                Ok((Value::String(out), children))
+            },
+            data => {
+                let status = self.eval_meta_inner(data, checked, &mut EvalCache::new());
+
+                Ok((self.meta_status_to_result(meta, status)?, Vec::new()))
+            },
+        }
+    }
+
+    fn explain_expr(&self, expr: &Expr, checked: &mut Vec<NodeId>) -> Result<Explanation, EvalError> {
+        match expr {
+            Expr::Literal(value) => Ok(Explanation {
+                step: Step::Literal,
+                value: value.clone(),
+                children: Vec::new(),
+            }),
+            Expr::Reference(ref_id) => {
+                let target = self.explain_node(*ref_id, checked)?;
+
+                Ok(Explanation {
+                    step: Step::Reference { id: *ref_id, path: self.path_of(*ref_id).unwrap_or_default() },
+                    value: target.value,
+                    children: target.children,
+                })
+            },
+            Expr::PathRef(path) => {
+                let resolved = self.resolve_path_ref(path, checked)?;
+                let target = self.explain_node(resolved, checked)?;
+
+                Ok(Explanation {
+                    step: Step::PathRef { path: path.clone(), resolved },
+                    value: target.value,
+                    children: target.children,
+                })
+            },
+            Expr::IdentRef(ref_id) => {
+                let source = self.explain_node(*ref_id, checked)?;
+                let Value::String(name) = &source.value else {
+                    return Err(EvalError::InvalidIdentRef(*ref_id));
+                };
+                let resolved = self.resolve_path_ref(name, checked)?;
+                let target = self.explain_node(resolved, checked)?;
+
+                Ok(Explanation {
+                    step: Step::IdentRef { id: *ref_id, name: name.clone(), resolved },
+                    value: target.value.clone(),
+                    children: vec![source, target],
+                })
+            },
+            Expr::Aggregate(aggregate) => {
+                let matches = self.aggregate_matches(aggregate, checked);
+
+                // Like `eval_aggregate` the value comes from the matches, so nothing is evaluated twice
+                let (value, children) = match aggregate.kind {
+                    AggregateKind::Sum => {
+                        let children = matches.into_iter()
+                            .map(|id| self.explain_node(id, checked))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        let mut sum: Integer = 0;
+
+                        for child in &children {
+                            let Value::Integer(value) = child.value else {
+                                return Err(EvalError::InvalidType);
+                            };
+                            sum = sum.checked_add(value).ok_or(EvalError::Overflow)?;
+                        }
+
+                        (Value::Integer(sum), children)
+                    },
+                    // Counting doesn't evaluate the matches, so any that fail are just left out of the explanation
+                    AggregateKind::Count => {
+                        let count = Value::Integer(matches.len() as _);
+
+                        (count, matches.into_iter().filter_map(|id| self.explain_node(id, checked).ok()).collect())
+                    },
+                };
+
+                Ok(Explanation {
+                    step: Step::Aggregate { kind: aggregate.kind, pattern: aggregate.pattern.clone() },
+                    value,
+                    children,
+                })
+            },
+            Expr::Lookup(lookup) => {
+                let key = self.explain_expr(&lookup.key, checked)?;
+                let (id, value) = self.eval_lookup(&lookup.table, key.value.clone(), checked)?;
+
+                Ok(Explanation {
+                    step: Step::Lookup { id, path: self.path_of(id).unwrap_or_default() },
+                    value,
+                    children: vec![key],
+                })
+            },
+            Expr::Has(inner) => {
+                let (value, children) = match self.explain_expr(inner, checked) {
+                    Ok(inner) => (Value::Integer(1), vec![inner]),
+                    Err(EvalError::MissingOptional(_)) => (Value::Integer(0), Vec::new()),
+                    Err(err) => return Err(err),
+                };
+
+                Ok(Explanation { step: Step::Has, value, children })
+            },
+            Expr::InfixOp(op) if op.kind == OpKind::Coalesce => {
+                let inner = match self.explain_expr(&op.lhs, checked) {
+                    Err(EvalError::MissingOptional(_)) => self.explain_expr(&op.rhs, checked)?,
+                    out => out?,
+                };
+
+                Ok(Explanation {
+                    step: Step::InfixOp(op.kind),
+                    value: inner.value.clone(),
+                    children: vec![inner],
+                })
+            },
+            Expr::InfixOp(op) => {
+                let lhs = self.explain_expr(&op.lhs, checked)?;
+                let rhs = self.explain_expr(&op.rhs, checked)?;
+
+                Ok(Explanation {
+                    step: Step::InfixOp(op.kind),
+                    value: op.apply(lhs.value.clone(), rhs.value.clone())?,
+                    children: vec![lhs, rhs],
+                })
+            },
+        }
+    }
+}
+
+impl Explanation {
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(f, "{:indent$}", "", indent = depth * 2)?;
+
+        match &self.step {
+            Step::Leaf { path, .. } => write!(f, "{path} = ")?,
+            Step::Meta { path, .. } => write!(f, "{path} (meta) = ")?,
+            Step::Literal => (),
+            Step::Modifier { source, op } => write!(f, "{op:?} from {source} = ")?,
+            Step::Contribution { source } => write!(f, "{source}: ")?,
+            Step::Reference { path, .. } => write!(f, "-> {path} = ")?,
+            Step::PathRef { path, .. } => write!(f, "-> {path} = ")?,
+            Step::IdentRef { name, .. } => write!(f, "-> {name:?} = ")?,
+            Step::Aggregate { kind, pattern } => write!(f, "{kind:?}({pattern}) = ")?,
+            Step::Lookup { path, .. } => write!(f, "lookup({path}) = ")?,
+            Step::Has => write!(f, "has = ")?,
+            Step::InfixOp(kind) => write!(f, "{kind:?} = ")?,
+        }
+
+        writeln!(f, "{}", self.value)?;
+
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Explanation {
+    /// Renders the explanation as an indented tree, one step per line
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
@@ -0,0 +1,521 @@
+use super::{
+    AddNodeError, Collection, ConcatElement, Constraint, Contribution, EditLeafError, Expr, IdentMode, IntFormat, Leaf, Meta, Metadata,
+    MetadataStart, Modifier, ModifierOp, Node, NodeId, ParseError, ParseErrorKind, Table, TableKey, Template, Value, ValueKind,
+};
+use super::meta::EditMetaError;
+use super::parse::Parser;
+use super::render::{render_path, render_segment, render_string};
+
+/// Why a template file couldn't be loaded, and on which line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoadError {
+    /// The line the problem is on, starting from 1
+    pub line: usize,
+    pub kind: LoadErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadErrorKind {
+    Parse(ParseError),
+    AddNode(AddNodeError),
+    EditLeaf(EditLeafError),
+    EditMeta(EditMetaError),
+    /// The line doesn't start with a known kind of node
+    UnknownKind(String),
+    /// The schema of a collection couldn't be loaded
+    Schema(Box<LoadError>),
+}
+
+/// Everything a line says about its node, other than where it goes
+enum Declaration {
+    Group,
+    Leaf { deferred: bool, optional: bool, kind: Option<NodeId>, value: Option<Expr> },
+    Meta(Metadata),
+    /// The schema is kept as source until the line is loaded, since loading it can fail in ways parsing can't
+    Collection { keyed: bool, schema: String },
+}
+
+impl Template {
+    /// Writes the template in the text format read by [`Template::load`]
+    /// 
+    /// Every node is written on its own line as its kind, its full path and anything else it contains, like
+    /// 
+    /// ```text
+    /// group abilities
+    /// leaf abilities.strength = 16
+    /// enum abilities.size small | medium | large
+    /// modifier abilities.strength.belt add 2 from "belt" category "enhancement"
+    /// ```
+    /// 
+    /// Parents always come before their children, but nodes may refer to nodes further down
+    pub fn save(&self) -> String {
+        self.save_lines().into_iter().map(|(_, line)| line + "\n").collect()
+    }
+
+    /// Writes every node as a line of the format read by [`Template::load`], along with its path
+    pub(super) fn save_lines(&self) -> Vec<(String, String)> {
+        let mut out = Vec::new();
+
+        for (path, node) in self.depth_first(0).skip(1) {
+            let rendered = render_path(&path);
+            let line = match node {
+                Node::Group(_) => format!("group {rendered}"),
+                Node::Leaf(leaf) => self.save_leaf(&rendered, leaf),
+                Node::Meta(meta) => match self.save_meta(&rendered, meta) {
+                    Some(line) => line,
+                    None => continue,
+                },
+            };
+
+            out.push((path, line));
+        }
+
+        out
+    }
+
+    /// Removes the path from a line written by [`Template::save_lines`] for the node at `path`, leaving its kind and
+    /// everything after the path
+    pub(super) fn line_without_path(line: &str, path: &str) -> String {
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.strip_prefix(render_path(path).as_str()).unwrap_or(rest);
+
+        format!("{kind}{rest}")
+    }
+
+    fn save_leaf(&self, path: &str, leaf: &Leaf) -> String {
+        let mut line = format!("leaf {path}");
+
+        if leaf.deferred {
+            line.push_str(" deferred");
+        }
+        if leaf.optional {
+            line.push_str(" optional");
+        }
+        if let ValueKind::Enum(id) = leaf.value_kind {
+            line.push_str(&format!(" kind {}", self.render_node(id)));
+        }
+        if let Some(expr) = &leaf.value {
+            line.push_str(&format!(" = {}", self.render_expr(expr)));
+        }
+
+        line
+    }
+
+    fn save_meta(&self, path: &str, meta: &Meta) -> Option<String> {
+        Some(match &meta.data {
+            Metadata::Common { value: Some(value), .. } => format!("common {path} = {}", self.render_expr(&value.clone().into())),
+            Metadata::Common { value: None, .. } => format!("common {path}"),
+            Metadata::CommonProxy { .. } => return None,
+            Metadata::Sum(contributions) => {
+                let contributions: Vec<_> = contributions.iter()
+                    .map(|contribution| format!("{}: {}", render_string(&contribution.source), self.render_expr(&contribution.expr)))
+                    .collect();
+
+                format!("sum {path} {}", contributions.join(", "))
+            },
+            Metadata::Ident(mode) => {
+                let mode = match mode {
+                    IdentMode::Name => "name",
+                    IdentMode::Path => "path",
+                    IdentMode::ParentName => "parent_name",
+                    IdentMode::Index => "index",
+                };
+
+                format!("ident {path} {mode}")
+            },
+            Metadata::Concat(elements) => {
+                let elements: Vec<_> = elements.iter().map(|element| {
+                    let mut out = self.render_expr(&element.expr);
+
+                    if element.format.sign {
+                        out.push_str(" signed");
+                    }
+                    if element.format.width > 0 {
+                        out.push_str(&format!(" pad {}", element.format.width));
+                    }
+
+                    out
+                }).collect();
+
+                format!("concat {path} {}", elements.join(", "))
+            },
+            Metadata::Constraint(constraint) => {
+                let (op, value) = match constraint {
+                    Constraint::GreaterThan(value) => (">", value),
+                    Constraint::GreaterOrEqual(value) => (">=", value),
+                    Constraint::LessThan(value) => ("<", value),
+                    Constraint::LessOrEqual(value) => ("<=", value),
+                    Constraint::Equal(value) => ("==", value),
+                };
+
+                format!("constraint {path} {op} {value}")
+            },
+            Metadata::Modifier(modifier) => {
+                let op = match modifier.op {
+                    ModifierOp::Add => "add",
+                    ModifierOp::Multiply => "multiply",
+                    ModifierOp::Override => "override",
+                    ModifierOp::Floor => "floor",
+                    ModifierOp::Cap => "cap",
+                };
+                let mut line = format!("modifier {path} {op} {} from {}", self.render_expr(&modifier.value), render_string(&modifier.source));
+
+                if modifier.priority != 0 {
+                    line.push_str(&format!(" priority {}", modifier.priority));
+                }
+                if let Some(category) = &modifier.category {
+                    line.push_str(&format!(" category {}", render_string(category)));
+                }
+
+                line
+            },
+            Metadata::Table(table) => {
+                let rows: Vec<_> = table.rows.iter().map(|(key, value)| {
+                    let key = match key {
+                        TableKey::Exact(key) => self.render_expr(&key.clone().into()),
+                        TableKey::Range(start, end) => format!("{start}..={end}"),
+                    };
+
+                    format!("{key} => {}", self.render_expr(&value.clone().into()))
+                }).collect();
+
+                format!("table {path} {}", rows.join(", "))
+            },
+            Metadata::Enum(variants) => {
+                let variants: Vec<_> = variants.iter().map(|variant| render_segment(variant)).collect();
+
+                format!("enum {path} {}", variants.join(" | "))
+            },
+            Metadata::Default(expr) => format!("default {path} = {}", self.render_expr(expr)),
+            Metadata::Collection(collection) => match collection.keyed {
+                true => format!("collection {path} keyed {}", render_string(&collection.schema.save())),
+                false => format!("collection {path} {}", render_string(&collection.schema.save())),
+            },
+        }.trim_end().to_owned())
+    }
+
+    /// Reads a template written by [`Template::save`]
+    /// 
+    /// Blank lines and lines starting with `#` are ignored
+    pub fn load(source: &str) -> Result<Template, LoadError> {
+        let mut template = Template::new();
+        template.load_into(source, 0)?;
+        template.clear_history();
+
+        Ok(template)
+    }
+
+    /// Adds the nodes of a template file below `root`, with every path in it resolved from `root`
+    ///
+    /// Nothing is added if the file can't be loaded
+    pub(super) fn load_into(&mut self, source: &str, root: NodeId) -> Result<(), LoadError> {
+        let lines: Vec<_> = source.lines().enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+
+        self.single_edit(|template| {
+            // Every node is created before anything is parsed so expressions can refer to nodes further down
+            for (number, line) in &lines {
+                template.load_node(line, root).map_err(|kind| LoadError { line: *number, kind })?;
+            }
+
+            // Leaves are filled in last, since setting their values checks them against enums and other metadata
+            for leaves in [false, true] {
+                for (number, line) in &lines {
+                    if (line.split_whitespace().next() == Some("leaf")) == leaves {
+                        template.load_declaration(line, root).map_err(|kind| LoadError { line: *number, kind })?;
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Creates the node declared by a line without filling it in
+    pub(super) fn load_node(&mut self, line: &str, root: NodeId) -> Result<(), LoadErrorKind> {
+        let mut parser = Parser::new(self, line);
+        let kind = parser.segment().map_err(LoadErrorKind::Parse)?;
+        parser.skip_whitespace();
+        let path_start = parser.position;
+        let path = parser.path().map_err(LoadErrorKind::Parse)?;
+
+        let (parent, name) = match path.rsplit_once('.') {
+            Some((parent, name)) => {
+                let parent = self.get_node_from(parent, root).ok_or_else(|| LoadErrorKind::Parse(ParseError {
+                    position: path_start,
+                    kind: ParseErrorKind::UnknownPath(parent.to_owned()),
+                }))?;
+
+                (parent, name)
+            },
+            None => (root, path.as_str()),
+        };
+
+        // Leaves and groups are stored in the inner group of a `Common` metanode
+        let inner = match self.get_meta_by_id(parent) {
+            Some(Meta { data: Metadata::Common { inner, .. }, .. }) => *inner,
+            _ => parent,
+        };
+
+        let start = match kind.as_str() {
+            "group" => return self.add_group_to(name, inner).map(|_| ()).map_err(LoadErrorKind::AddNode),
+            "leaf" => return self.add_leaf_to(name, inner, false).map(|_| ()).map_err(LoadErrorKind::AddNode),
+            "common" => MetadataStart::Common,
+            "sum" => MetadataStart::Sum,
+            "ident" => MetadataStart::Ident,
+            "concat" => MetadataStart::Concat,
+            "constraint" => MetadataStart::Constraint(Constraint::Equal(0)),
+            "modifier" => MetadataStart::Modifier(ModifierOp::Add),
+            "table" => MetadataStart::Table,
+            "enum" => MetadataStart::Enum,
+            "default" => MetadataStart::Default,
+            "collection" => MetadataStart::Collection,
+            _ => return Err(LoadErrorKind::UnknownKind(kind)),
+        };
+
+        self.add_meta_to(name, parent, start).map(|_| ()).map_err(LoadErrorKind::AddNode)
+    }
+
+    /// Fills in the node declared by a line
+    pub(super) fn load_declaration(&mut self, line: &str, root: NodeId) -> Result<(), LoadErrorKind> {
+        let mut parser = Parser::new(self, line).with_root(root);
+        let kind = parser.segment().map_err(LoadErrorKind::Parse)?;
+        parser.skip_whitespace();
+        let id = parser.node().map_err(LoadErrorKind::Parse)?;
+        let declaration = parser.declaration(&kind, self.get_meta_by_id(id)).map_err(LoadErrorKind::Parse)?;
+
+        match declaration {
+            Declaration::Group => (),
+            Declaration::Leaf { deferred, optional, kind, value } => {
+                if let Some(expr) = value {
+                    self.set_leaf_expr(id, expr).map_err(LoadErrorKind::EditLeaf)?;
+                }
+                if let Some(kind) = kind {
+                    self.set_leaf_kind(id, ValueKind::Enum(kind)).map_err(LoadErrorKind::EditLeaf)?;
+                }
+                self.set_leaf_optional(id, optional).map_err(LoadErrorKind::EditLeaf)?;
+
+                if let Some(leaf) = self.get_mut_leaf_by_id(id) {
+                    leaf.deferred = deferred;
+                }
+            },
+            Declaration::Meta(data) => {
+                let mut handle = super::MetaHandle { id, template: self };
+                handle.set_value(data).map_err(LoadErrorKind::EditMeta)?;
+            },
+            Declaration::Collection { keyed, schema } => {
+                let schema = Template::load(&schema).map_err(|err| LoadErrorKind::Schema(Box::new(err)))?;
+                let mut handle = super::MetaHandle { id, template: self };
+                handle.set_value(Metadata::Collection(Collection { schema, keyed })).map_err(LoadErrorKind::EditMeta)?;
+            },
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Parser<'a> {
+    /// Parses everything after the path of a line declaring a node of the given kind
+    fn declaration(&mut self, kind: &str, meta: Option<&Meta>) -> Result<Declaration, ParseError> {
+        let declaration = match kind {
+            "group" => Declaration::Group,
+            "leaf" => {
+                let deferred = self.keyword("deferred");
+                let optional = self.keyword("optional");
+                let kind = match self.keyword("kind") {
+                    true => {
+                        self.skip_whitespace();
+                        Some(self.node()?)
+                    },
+                    false => None,
+                };
+                let value = match self.eat("=") {
+                    true => Some(self.expr(0)?),
+                    false => None,
+                };
+
+                Declaration::Leaf { deferred, optional, kind, value }
+            },
+            "common" => {
+                let inner = match meta {
+                    Some(Meta { data: Metadata::Common { inner, .. }, .. }) => *inner,
+                    _ => 0,
+                };
+                let value = match self.eat("=") {
+                    true => Some(self.literal()?),
+                    false => None,
+                };
+
+                Declaration::Meta(Metadata::Common { inner, value })
+            },
+            "sum" => Declaration::Meta(Metadata::Sum(self.list(|parser| {
+                parser.skip_whitespace();
+                let source = parser.string()?;
+                parser.expect(":")?;
+
+                Ok(Contribution { source, expr: parser.expr(0)? })
+            })?)),
+            "ident" => {
+                self.skip_whitespace();
+                let mode = match self.segment()?.as_str() {
+                    "name" => IdentMode::Name,
+                    "path" => IdentMode::Path,
+                    "parent_name" => IdentMode::ParentName,
+                    "index" => IdentMode::Index,
+                    word => return Err(self.error(ParseErrorKind::UnexpectedWord(word.to_owned()))),
+                };
+
+                Declaration::Meta(Metadata::Ident(mode))
+            },
+            "concat" => Declaration::Meta(Metadata::Concat(self.list(|parser| {
+                let expr = parser.expr(0)?;
+                let sign = parser.keyword("signed");
+                let width = match parser.keyword("pad") {
+                    true => {
+                        parser.skip_whitespace();
+                        parser.integer()?.max(0) as usize
+                    },
+                    false => 0,
+                };
+
+                Ok(ConcatElement { expr, format: IntFormat { sign, width } })
+            })?)),
+            "constraint" => {
+                let constraint: fn(_) -> _ = if self.eat(">=") {
+                    Constraint::GreaterOrEqual
+                } else if self.eat(">") {
+                    Constraint::GreaterThan
+                } else if self.eat("<=") {
+                    Constraint::LessOrEqual
+                } else if self.eat("<") {
+                    Constraint::LessThan
+                } else {
+                    self.expect("==")?;
+                    Constraint::Equal
+                };
+                self.skip_whitespace();
+
+                Declaration::Meta(Metadata::Constraint(constraint(self.integer()?)))
+            },
+            "modifier" => {
+                self.skip_whitespace();
+                let op = match self.segment()?.as_str() {
+                    "add" => ModifierOp::Add,
+                    "multiply" => ModifierOp::Multiply,
+                    "override" => ModifierOp::Override,
+                    "floor" => ModifierOp::Floor,
+                    "cap" => ModifierOp::Cap,
+                    word => return Err(self.error(ParseErrorKind::UnexpectedWord(word.to_owned()))),
+                };
+                let value = self.expr(0)?;
+
+                if !self.keyword("from") {
+                    return Err(self.unexpected());
+                }
+                self.skip_whitespace();
+                let mut modifier = Modifier::new(&self.string()?, op, value);
+
+                if self.keyword("priority") {
+                    self.skip_whitespace();
+                    modifier.priority = self.integer()?;
+                }
+                if self.keyword("category") {
+                    self.skip_whitespace();
+                    modifier.category = Some(self.string()?);
+                }
+
+                Declaration::Meta(Metadata::Modifier(modifier))
+            },
+            "table" => Declaration::Meta(Metadata::Table(Table { rows: self.list(|parser| {
+                let key = match parser.literal()? {
+                    Value::Integer(start) if parser.eat("..=") => {
+                        parser.skip_whitespace();
+                        TableKey::Range(start, parser.integer()?)
+                    },
+                    key => TableKey::Exact(key),
+                };
+                parser.expect("=>")?;
+
+                Ok((key, parser.literal()?))
+            })? })),
+            "enum" => {
+                let mut variants = Vec::new();
+
+                self.skip_whitespace();
+                if self.peek().is_some() {
+                    loop {
+                        self.skip_whitespace();
+                        variants.push(self.segment()?);
+
+                        if !self.eat("|") {
+                            break;
+                        }
+                    }
+                }
+
+                Declaration::Meta(Metadata::Enum(variants))
+            },
+            "default" => {
+                self.expect("=")?;
+
+                Declaration::Meta(Metadata::Default(self.expr(0)?))
+            },
+            "collection" => {
+                let keyed = self.keyword("keyed");
+                self.skip_whitespace();
+
+                Declaration::Collection { keyed, schema: self.string()? }
+            },
+            _ => return Err(self.unexpected()),
+        };
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some(_) => Err(self.error(ParseErrorKind::TrailingInput)),
+            None => Ok(declaration),
+        }
+    }
+
+    /// Skips whitespace and consumes `word` if it's the next whole word
+    fn keyword(&mut self, word: &str) -> bool {
+        let start = self.position;
+
+        self.skip_whitespace();
+        if self.segment().is_ok_and(|segment| segment == word) {
+            true
+        } else {
+            self.position = start;
+            false
+        }
+    }
+
+    /// Parses an expression which has to be a literal value
+    fn literal(&mut self) -> Result<Value, ParseError> {
+        let start = self.position;
+
+        match self.expr(0)? {
+            Expr::Literal(value) => Ok(value),
+            _ => Err(ParseError { position: start, kind: ParseErrorKind::NotLiteral }),
+        }
+    }
+
+    /// Parses items separated by commas until the end of the line
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, ParseError>) -> Result<Vec<T>, ParseError> {
+        let mut items = Vec::new();
+
+        self.skip_whitespace();
+        if self.peek().is_none() {
+            return Ok(items);
+        }
+
+        loop {
+            items.push(item(self)?);
+
+            if !self.eat(",") {
+                return Ok(items);
+            }
+        }
+    }
+}
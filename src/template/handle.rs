@@ -18,7 +18,7 @@ impl Handle for Template {
 
 impl<'a> Handle for LeafHandle<'a> {
     fn get_template(&self) -> &Template {
-        &self.template
+        self.template
     }
 
     fn get_template_mut(&mut self) -> &mut Template {
@@ -32,7 +32,7 @@ impl<'a> Handle for LeafHandle<'a> {
 
 impl<'a> Handle for GroupHandle<'a> {
     fn get_template(&self) -> &Template {
-        &self.template
+        self.template
     }
 
     fn get_template_mut(&mut self) -> &mut Template {
@@ -46,7 +46,7 @@ impl<'a> Handle for GroupHandle<'a> {
 
 impl<'a> Handle for MetaHandle<'a> {
     fn get_template(&self) -> &Template {
-        &self.template
+        self.template
     }
 
     fn get_template_mut(&mut self) -> &mut Template {
@@ -63,7 +63,7 @@ pub trait Handle {
     fn get_template_mut(&mut self) -> &mut Template;
     fn get_id(&self) -> NodeId;
 
-    fn get_meta_handle(&mut self, path: &str) -> Option<MetaHandle> {
+    fn get_meta_handle(&mut self, path: &str) -> Option<MetaHandle<'_>> {
         let id = self.get_id();
         let template = self.get_template_mut();
         let node = template.nodes.get(&template.get_node_from(path, id)?).map(|(node, _)| node)?;
@@ -85,7 +85,7 @@ pub trait Handle {
         }
     }
 
-    fn add_meta(&mut self, name: &str, start: MetadataStart) -> Result<MetaHandle, AddNodeError> {
+    fn add_meta(&mut self, name: &str, start: MetadataStart) -> Result<MetaHandle<'_>, AddNodeError> {
         let id = self.get_id();
         let template = self.get_template_mut();
         template.add_meta_to(name, id, start)
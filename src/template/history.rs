@@ -0,0 +1,300 @@
+use std::{collections::HashMap, mem, panic::{self, AssertUnwindSafe}, sync::Arc};
+
+use super::{inherit::Nodes, Node, NodeId, Template};
+
+/// The whole template at a checkpoint
+#[derive(Clone, Debug)]
+struct Snapshot {
+    nodes: Nodes,
+    next_id: NodeId,
+    base: Option<Arc<Template>>,
+}
+
+/// What an edit changed, as it was before the edit, which is all that's needed to undo it
+#[derive(Clone, Debug, Default)]
+pub(super) struct EditRecord {
+    /// Each node the edit changed, or `None` for nodes the edit added
+    nodes: HashMap<NodeId, Option<(Node, String)>>,
+    next_id: Option<NodeId>,
+    base: Option<Option<Arc<Template>>>,
+}
+
+impl EditRecord {
+    /// Adds the changes from a later part of the same edit, keeping whatever was recorded first
+    fn extend(&mut self, later: EditRecord) {
+        for (id, node) in later.nodes {
+            self.nodes.entry(id).or_insert(node);
+        }
+
+        self.next_id = self.next_id.or(later.next_id);
+        self.base = self.base.take().or(later.base);
+    }
+}
+
+/// How many edits can be undone unless [`Template::set_history_limit`] is used
+const DEFAULT_LIMIT: usize = 100;
+
+/// Edits that can be undone and redone, along with named checkpoints
+#[derive(Clone, Debug)]
+pub(super) struct History {
+    /// What each edit changed, most recent last
+    undo: Vec<EditRecord>,
+    /// What each undo changed, most recent last
+    redo: Vec<EditRecord>,
+    checkpoints: HashMap<String, Snapshot>,
+    /// What the edit being made has changed so far, boxed since every collection schema holds a template
+    current: Box<EditRecord>,
+    /// While this is above 0, edits are part of a larger edit which is recorded as a whole
+    paused: usize,
+    /// The most edits that are kept to be undone
+    limit: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            checkpoints: HashMap::new(),
+            current: Box::default(),
+            paused: 0,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl History {
+    /// Gets a history with nothing in it and the same limit
+    pub(super) fn emptied(&self) -> History {
+        History { limit: self.limit, ..History::default() }
+    }
+}
+
+impl Template {
+    /// Puts the template back how it was before the last edit, returning `false` if there's nothing to undo
+    ///
+    /// Dependencies are put back along with everything else, and anything the edit could have changed the value of
+    /// is evaluated again
+    pub fn undo(&mut self) -> bool {
+        let Some(record) = self.history.undo.pop() else {
+            return false;
+        };
+
+        let inverse = self.revert(record);
+        self.history.redo.push(inverse);
+
+        true
+    }
+
+    /// Makes the last edit that was undone again, returning `false` if there's nothing to redo
+    ///
+    /// Anything undone can only be redone until another edit is made
+    pub fn redo(&mut self) -> bool {
+        let Some(record) = self.history.redo.pop() else {
+            return false;
+        };
+
+        let inverse = self.revert(record);
+        self.history.undo.push(inverse);
+
+        true
+    }
+
+    /// Remembers the template as it is now, so it can be restored with [`Template::restore`]
+    ///
+    /// Any checkpoint with the same name is replaced. Unlike edits, each checkpoint keeps a copy of every node
+    pub fn checkpoint(&mut self, name: &str) {
+        let snapshot = Snapshot { nodes: self.nodes.clone(), next_id: self.next_id, base: self.base.clone() };
+        self.history.checkpoints.insert(name.to_owned(), snapshot);
+    }
+
+    /// Puts the template back how it was at a checkpoint, returning `false` if there's no checkpoint with the name
+    ///
+    /// This is an edit like any other, so it can be undone
+    pub fn restore(&mut self, name: &str) -> bool {
+        let Some(snapshot) = self.history.checkpoints.get(name).cloned() else {
+            return false;
+        };
+
+        let outermost = self.begin_edit();
+        self.replace_nodes(snapshot.nodes, snapshot.next_id);
+        self.set_base(snapshot.base);
+        self.record_edit(outermost);
+
+        true
+    }
+
+    /// Gets the names of every checkpoint, sorted
+    pub fn checkpoints(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.history.checkpoints.keys().map(String::as_str).collect();
+        names.sort();
+
+        names
+    }
+
+    /// Forgets every edit and checkpoint, which also frees the memory used to store them
+    pub fn clear_history(&mut self) {
+        self.history = self.history.emptied();
+    }
+
+    /// Sets how many edits can be undone, forgetting the oldest edits past the limit
+    ///
+    /// Each edit keeps a copy of the nodes it changed as they were before the edit, so edits to many nodes at once
+    /// take more memory
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history.limit = limit;
+
+        let excess = self.history.undo.len().saturating_sub(limit);
+        self.history.undo.drain(..excess);
+    }
+
+    /// Starts recording an edit, returning `false` if the edit is part of a larger edit which is recorded instead
+    pub(super) fn begin_edit(&mut self) -> bool {
+        let outermost = self.history.paused == 0;
+
+        // Anything left over is from an edit that failed part way through, which was never recorded
+        if outermost {
+            *self.history.current = EditRecord::default();
+        }
+
+        outermost
+    }
+
+    /// Records a finished edit, given what [`Template::begin_edit`] returned before it
+    pub(super) fn record_edit(&mut self, outermost: bool) {
+        if outermost {
+            let record = mem::take(&mut self.history.current);
+            self.history.undo.push(*record);
+            self.history.redo.clear();
+
+            if self.history.undo.len() > self.history.limit {
+                self.history.undo.remove(0);
+            }
+        }
+    }
+
+    /// Makes several edits as one, which is recorded as a single edit if it succeeds and undone if it fails or panics
+    pub(super) fn single_edit<T, E>(&mut self, edit: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        let outermost = self.begin_edit();
+        // Only the changes made here are undone if this fails, not the rest of a larger edit
+        let outer = mem::take(&mut self.history.current);
+
+        self.history.paused += 1;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| edit(self)));
+        self.history.paused -= 1;
+
+        let record = mem::replace(&mut self.history.current, outer);
+
+        let result = match result {
+            Ok(result) => result,
+            Err(payload) => {
+                self.revert(*record);
+                panic::resume_unwind(payload);
+            },
+        };
+
+        match result {
+            Ok(_) => {
+                self.history.current.extend(*record);
+                self.record_edit(outermost);
+            },
+            Err(_) => {
+                self.revert(*record);
+            },
+        }
+
+        result
+    }
+
+    /// Gets a node to change as part of an edit, remembering how it was first
+    pub(super) fn node_mut(&mut self, id: NodeId) -> Option<&mut (Node, String)> {
+        if !self.history.current.nodes.contains_key(&id) {
+            let before = self.nodes.get(&id)?.clone();
+            self.history.current.nodes.insert(id, Some(before));
+        }
+
+        self.nodes.get_mut(&id)
+    }
+
+    /// Adds or replaces a node as part of an edit
+    pub(super) fn insert_node(&mut self, id: NodeId, node: (Node, String)) {
+        if !self.history.current.nodes.contains_key(&id) {
+            self.history.current.nodes.insert(id, self.nodes.get(&id).cloned());
+        }
+
+        self.nodes.insert(id, node);
+    }
+
+    /// Removes a node as part of an edit, without touching its parent or children
+    pub(super) fn take_node(&mut self, id: NodeId) -> Option<(Node, String)> {
+        let node = self.nodes.remove(&id)?;
+        self.history.current.nodes.entry(id).or_insert_with(|| Some(node.clone()));
+
+        Some(node)
+    }
+
+    /// Sets the ID the next node will get as part of an edit
+    pub(super) fn set_next_id(&mut self, next_id: NodeId) {
+        self.history.current.next_id.get_or_insert(self.next_id);
+        self.next_id = next_id;
+    }
+
+    /// Sets the base as part of an edit
+    pub(super) fn set_base(&mut self, base: Option<Arc<Template>>) {
+        let old = mem::replace(&mut self.base, base);
+        self.history.current.base.get_or_insert(old);
+    }
+
+    /// Replaces every node as part of an edit
+    pub(super) fn replace_nodes(&mut self, nodes: Nodes, next_id: NodeId) {
+        let removed: Vec<NodeId> = self.nodes.keys().copied().filter(|id| !nodes.contains_key(id)).collect();
+
+        for id in removed {
+            self.take_node(id);
+        }
+
+        for (id, node) in nodes.iter() {
+            self.insert_node(*id, node.clone());
+        }
+
+        self.set_next_id(next_id);
+        self.watch_all();
+    }
+
+    /// Puts back everything a record changed, returning a record which puts it back again
+    fn revert(&mut self, record: EditRecord) -> EditRecord {
+        let mut inverse = EditRecord::default();
+        let mut changed = Vec::new();
+
+        for (id, node) in record.nodes {
+            let current = match node {
+                Some(node) => self.nodes.insert(id, node),
+                None => self.nodes.remove(&id),
+            };
+            inverse.nodes.insert(id, current);
+            changed.push(id);
+        }
+
+        if let Some(next_id) = record.next_id {
+            inverse.next_id = Some(mem::replace(&mut self.next_id, next_id));
+        }
+
+        if let Some(base) = record.base {
+            inverse.base = Some(mem::replace(&mut self.base, base));
+        }
+
+        // The paths nodes are watching aren't part of the record, so they're found again from the nodes put back
+        for id in &changed {
+            if self.nodes.contains_key(id) {
+                self.watch(*id);
+            } else {
+                self.unwatch(*id);
+            }
+        }
+
+        // Values cached since the edit may have used the nodes that were put back
+        self.invalidate_all_dependents(changed);
+
+        inverse
+    }
+}
@@ -0,0 +1,190 @@
+use std::{collections::HashMap, ops::Index, sync::Arc};
+
+use super::{Conflict, LoadError, MergeError, Node, NodeId, Template};
+
+/// Every node in a template by ID, where nodes a derived template hasn't changed are read from its base
+///
+/// A node is copied out of the base the first time it changes, which includes caching its value
+#[derive(Clone, Debug, Default)]
+pub(super) struct Nodes {
+    /// Nodes added or changed since the base, or `None` for nodes of the base that were removed
+    own: HashMap<NodeId, Option<(Node, String)>>,
+    /// The nodes of the base, shared with every template derived from it
+    shared: Option<Arc<HashMap<NodeId, (Node, String)>>>,
+}
+
+/// A difference between a derived template and its base, by path
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Override {
+    /// The node only exists in the derived template
+    Added(String),
+    /// The node exists in both but the derived template changed it
+    Changed(String),
+    /// The derived template removed the node
+    Removed(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RebaseError {
+    /// The template wasn't derived from another
+    NoBase,
+    Conflicts(Vec<Conflict>),
+    /// The overrides don't make sense on top of the new base, like a node added to a group the derived template removed
+    Invalid(LoadError),
+}
+
+impl Nodes {
+    pub(super) fn get(&self, id: &NodeId) -> Option<&(Node, String)> {
+        match self.own.get(id) {
+            Some(node) => node.as_ref(),
+            None => self.shared.as_ref()?.get(id),
+        }
+    }
+
+    /// Gets a node to change, copying it out of the base first if it's only there
+    pub(super) fn get_mut(&mut self, id: &NodeId) -> Option<&mut (Node, String)> {
+        if !self.own.contains_key(id) {
+            let node = self.shared.as_ref()?.get(id)?.clone();
+            self.own.insert(*id, Some(node));
+        }
+
+        self.own.get_mut(id)?.as_mut()
+    }
+
+    pub(super) fn insert(&mut self, id: NodeId, node: (Node, String)) -> Option<(Node, String)> {
+        let old = self.own.insert(id, Some(node));
+
+        match old {
+            Some(old) => old,
+            None => self.shared.as_ref()?.get(&id).cloned(),
+        }
+    }
+
+    pub(super) fn remove(&mut self, id: &NodeId) -> Option<(Node, String)> {
+        let in_base = self.shared.as_ref().and_then(|shared| shared.get(id));
+
+        // Nodes of the base are left behind as removed, so they aren't read from the base again
+        match in_base {
+            Some(node) => self.own.insert(*id, None).unwrap_or_else(|| Some(node.clone())),
+            None => self.own.remove(id).flatten(),
+        }
+    }
+
+    pub(super) fn contains_key(&self, id: &NodeId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub(super) fn iter(&self) -> impl Iterator<Item = (&NodeId, &(Node, String))> {
+        let own = self.own.iter().filter_map(|(id, node)| Some((id, node.as_ref()?)));
+        let shared = self.shared.iter().flat_map(|shared| shared.iter()).filter(|(id, _)| !self.own.contains_key(id));
+
+        own.chain(shared)
+    }
+
+    pub(super) fn keys(&self) -> impl Iterator<Item = &NodeId> {
+        self.iter().map(|(id, _)| id)
+    }
+
+    pub(super) fn values(&self) -> impl Iterator<Item = &(Node, String)> {
+        self.iter().map(|(_, node)| node)
+    }
+
+    /// Gets the same nodes with all of them in the base, so they can be shared by derived templates
+    fn shared(&self) -> Nodes {
+        match &self.shared {
+            Some(_) if self.own.is_empty() => self.clone(),
+            _ => Nodes {
+                own: HashMap::new(),
+                shared: Some(Arc::new(self.iter().map(|(id, node)| (*id, node.clone())).collect())),
+            },
+        }
+    }
+}
+
+impl Index<&NodeId> for Nodes {
+    type Output = (Node, String);
+
+    fn index(&self, id: &NodeId) -> &Self::Output {
+        self.get(id).expect("the node exists")
+    }
+}
+
+impl Template {
+    /// Creates a template which starts out the same as this one, and can be rebased when this one changes
+    ///
+    /// The derived template only keeps the nodes it adds or changes, and reads every other node from a copy of this
+    /// template kept as its base, which templates derived from it share. Later changes to this template only reach
+    /// it through [`Template::rebase`], which brings in changes to nodes the derived template didn't change while
+    /// keeping its own additions and changes
+    pub fn derive(&self) -> Template {
+        let base = self.as_base();
+
+        Template {
+            nodes: base.nodes.clone(),
+            next_id: self.next_id,
+            trace: self.trace.clone(),
+            history: self.history.emptied(),
+            pending_invalidation: None,
+            watches: self.watches.clone(),
+            base: Some(Arc::new(base)),
+        }
+    }
+
+    /// Copies the nodes of this template to be kept as the base of another, without anything only needed for editing
+    fn as_base(&self) -> Template {
+        Template {
+            nodes: self.nodes.shared(),
+            next_id: self.next_id,
+            watches: self.watches.clone(),
+            ..Template::new()
+        }
+    }
+
+    /// The template this one was derived from, as it was when this one was derived or last rebased
+    pub fn base(&self) -> Option<&Template> {
+        self.base.as_deref()
+    }
+
+    /// Gets every node added, changed or removed since this template was derived, parents first
+    pub fn overrides(&self) -> Vec<Override> {
+        let Some(base) = &self.base else {
+            return Vec::new();
+        };
+
+        let base_lines = base.save_lines();
+        let own_lines = self.save_lines();
+        let base_by_path: HashMap<_, _> = base_lines.iter().map(|(path, line)| (path, line)).collect();
+        let own_by_path: HashMap<_, _> = own_lines.iter().map(|(path, line)| (path, line)).collect();
+
+        let changed = own_lines.iter().filter_map(|(path, line)| match base_by_path.get(path) {
+            None => Some(Override::Added(path.clone())),
+            Some(base_line) if *base_line != line => Some(Override::Changed(path.clone())),
+            Some(_) => None,
+        });
+        let removed = base_lines.iter()
+            .filter(|(path, _)| !own_by_path.contains_key(path))
+            .map(|(path, _)| Override::Removed(path.clone()));
+
+        changed.chain(removed).collect()
+    }
+
+    /// Moves this template onto a new version of its base, keeping its overrides
+    /// 
+    /// Nothing is changed if any override conflicts with a change made to the base. Like [`Template::merge`], the
+    /// changes are made in place, so every node that's still there keeps its ID. Nodes the change to the base didn't
+    /// touch are still read from the old base
+    pub fn rebase(&mut self, new_base: &Template) -> Result<(), RebaseError> {
+        let old_base = self.base.clone().ok_or(RebaseError::NoBase)?;
+
+        // The trace hook, history and checkpoints all stay, and the rebase can be undone like any other edit
+        self.single_edit(|template| {
+            template.merge(&old_base, new_base).map_err(|err| match err {
+                MergeError::Conflicts(conflicts) => RebaseError::Conflicts(conflicts),
+                MergeError::Invalid(err) => RebaseError::Invalid(err),
+            })?;
+            template.set_base(Some(Arc::new(new_base.as_base())));
+
+            Ok(())
+        })
+    }
+}
@@ -11,6 +11,8 @@ pub enum Value {
     String(String),
     /// A list of values
     List(Vec<Expr>),
+    /// One of the variants of the `Enum` metanode `id`
+    Enum { id: NodeId, variant: String },
 }
 
 /// Empty values for type resolution
@@ -20,6 +22,8 @@ pub enum ValueKind {
     Integer,
     String,
     List,
+    /// A value from the `Enum` metanode with this ID
+    Enum(NodeId),
 }
 
 /// An expression to be evaluated before being referenced
@@ -27,11 +31,48 @@ pub enum ValueKind {
 pub enum Expr {
     Literal(Value),
     Reference(NodeId),
+    /// References the node at a path, which may be relative to the node containing the expression
+    /// 
+    /// See [`Template::resolve_path`](super::Template::resolve_path) for the path syntax
+    PathRef(String),
     /// This one can be used to reference whatever has the name contained in the referenced node
+    /// 
+    /// The name may use the same relative syntax as `PathRef`
     IdentRef(NodeId),
+    /// Combines the values of every node matching a pattern, like `sum(equipment.*.weight)`
+    Aggregate(Aggregate),
+    /// Evaluates to 1 if the expression has a value and 0 if it's missing an optional leaf, like `has(x)`
+    Has(Box<Expr>),
+    /// Looks up the value for a key in a `Table` metanode, like `lookup(proficiency_table, level)`
+    Lookup(Box<Lookup>),
     InfixOp(Box<InfixOp>),
 }
 
+/// A lookup of a key in a table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Lookup {
+    /// The path to the `Table` metanode, which may be relative to the node containing the expression
+    pub table: String,
+    pub key: Expr,
+}
+
+/// A function applied to every leaf found by [`Template::query`](super::Template::query)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Aggregate {
+    pub kind: AggregateKind,
+    /// The pattern to match, which may be relative to the node containing the expression
+    pub pattern: String,
+}
+
+/// Types of aggregates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AggregateKind {
+    /// Adds together the integer values of all matches
+    Sum,
+    /// Counts the matches other than groups, without evaluating them, so matches that can't be evaluated still count
+    Count,
+}
+
 /// An operation with a left hand side (lhs) and a right hand side (rhs)
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct InfixOp {
@@ -47,8 +88,20 @@ pub enum OpKind {
     Sub,
     Mul,
     Div,
+    /// Raises the left side to the power of the right side, which can't be negative
     Pow,
+    /// Negates the left side, written as a prefix like `-strength`
+    /// 
+    /// The right side is ignored, and is `0` when parsed
     Neg,
+    /// Evaluates to 1 if both sides are equal and 0 otherwise
+    /// 
+    /// Enum values are equal to strings containing the name of their variant
+    Eq,
+    /// Evaluates to 0 if both sides are equal and 1 otherwise
+    Ne,
+    /// Evaluates to the right side if the left side is missing an optional leaf, like `x ?? 0`
+    Coalesce,
 }
 
 impl From<&Value> for ValueKind {
@@ -57,12 +110,13 @@ impl From<&Value> for ValueKind {
             Value::Integer(_) => ValueKind::Integer,
             Value::String(_) => ValueKind::String,
             Value::List(_) => ValueKind::List,
+            Value::Enum { id, .. } => ValueKind::Enum(*id),
         }
     }
 }
 
 impl From<&InfixOp> for ValueKind {
-    fn from(value: &InfixOp) -> Self {
+    fn from(_value: &InfixOp) -> Self {
         // For now infix ops can only be used on integers
         ValueKind::Integer
     }
@@ -113,10 +167,27 @@ impl<'a> LeafHandle<'a> {
         Ok(self)
     }
 
+    pub fn set_optional(&mut self, optional: bool) -> Result<&mut Self, EditLeafError> {
+        self.template.set_leaf_optional(self.id, optional)?;
+
+        Ok(self)
+    }
+
+    pub fn set_kind(&mut self, kind: ValueKind) -> Result<&mut Self, EditLeafError> {
+        self.template.set_leaf_kind(self.id, kind)?;
+
+        Ok(self)
+    }
+
+    /// Gets the valid values for this leaf if it holds an enum
+    pub fn options(&self) -> Option<&[String]> {
+        self.template.options(self.id)
+    }
+
     pub fn get_value(&self) -> Option<&Expr> {
         match &self.template.nodes.get(&self.id)?.0 {
             Node::Leaf(leaf) => {
-                (&leaf).value.as_ref()
+                leaf.value.as_ref()
             },
             _ => None
         }
@@ -1,30 +1,54 @@
 use crate::{template::{EvalError, OpKind}, Template};
 
-use super::{InfixOp, Value};
+use super::{InfixOp, Value, Integer};
 
 impl InfixOp {
-    pub fn eval<'a>(&self, template: &Template) -> Result<Value, EvalError> {
+    pub fn eval(&self, template: &Template) -> Result<Value, EvalError> {
+        self.apply(template.eval_expr(&self.lhs)?, template.eval_expr(&self.rhs)?)
+    }
+
+    /// Applies this operation to already evaluated operands
+    pub fn apply(&self, lhs: Value, rhs: Value) -> Result<Value, EvalError> {
         match self.kind {
+            kind @ OpKind::Eq | kind @ OpKind::Ne => {
+                let equal = match (&lhs, &rhs) {
+                    (Value::Enum { variant, .. }, Value::String(string))
+                    | (Value::String(string), Value::Enum { variant, .. }) => variant == string,
+                    _ => lhs == rhs,
+                };
+
+                Ok(Value::Integer((equal == (kind == OpKind::Eq)) as Integer))
+            },
             kind @ OpKind::Add 
             | kind @ OpKind::Sub
             | kind @ OpKind::Div
             | kind @ OpKind::Mul
             | kind @ OpKind::Pow => {
-                match (template.eval_expr(&self.lhs)?, template.eval_expr(&self.rhs)?) {
+                match (lhs, rhs) {
+                    (Value::Integer(_), Value::Integer(0)) if kind == OpKind::Div => Err(EvalError::DivisionByZero),
                     (Value::Integer(lhs), Value::Integer(rhs)) => {
-                        Ok(Value::Integer(match kind {
-                            OpKind::Add => lhs + rhs,
-                            OpKind::Sub => lhs - rhs,
-                            OpKind::Div => lhs / rhs,
-                            OpKind::Mul => lhs * rhs,
-                            OpKind::Pow => lhs * rhs,
-                            _ => unreachable!(),
-                        }))
+                        let out = match kind {
+                            OpKind::Add => lhs.checked_add(rhs),
+                            OpKind::Sub => lhs.checked_sub(rhs),
+                            OpKind::Div => lhs.checked_div(rhs),
+                            OpKind::Mul => lhs.checked_mul(rhs),
+                            OpKind::Pow if rhs < 0 => return Err(EvalError::NegativeExponent),
+                            OpKind::Pow => u32::try_from(rhs).ok().and_then(|rhs| lhs.checked_pow(rhs)),
+                            _ => None,
+                        };
+
+                        out.map(Value::Integer).ok_or(EvalError::Overflow)
                     },
                     _ => Err(EvalError::InvalidType)
                 }
             },
-            _ => unreachable!("That's not an infix operator"),
+            // Only the left side is negated, the right side is a placeholder
+            OpKind::Neg => match lhs {
+                Value::Integer(lhs) => lhs.checked_neg().map(Value::Integer).ok_or(EvalError::Overflow),
+                _ => Err(EvalError::InvalidType),
+            },
+            // The left side is only missing if evaluating it failed, so it's always used here
+            OpKind::Coalesce => Ok(lhs),
         }
     }
-}
\ No newline at end of file
+}
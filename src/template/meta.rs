@@ -1,13 +1,13 @@
 use crate::{NodeTree, AddNodeError};
 
-use super::{MetaHandle, Metadata, NodeHandle, Group, Node, LeafHandle, GroupHandle, Leaf, EditLeafError, Meta, NodeId};
+use super::{MetaHandle, Metadata, NodeHandle, Group, Node, LeafHandle, GroupHandle, Leaf, NodeId, Meta, Contribution, Expr};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum EditMetaError {
     WrongKind,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PushCommonError {
     CommonNotExists,
     ParentNotGroup,
@@ -29,7 +29,7 @@ impl<'a> MetaHandle<'a> {
         }
     }
 
-    pub fn get_handle(&mut self, path: &str) -> Option<NodeHandle> {
+    pub fn get_handle(&mut self, path: &str) -> Option<NodeHandle<'_>> {
         if let Some(group) = self.check_common() {
             let group_handle = GroupHandle { id: group, template: self.template };
             let node_handle = group_handle.get_node(path)?;
@@ -45,14 +45,14 @@ impl<'a> MetaHandle<'a> {
         }
     }
 
-    pub fn get_leaf_handle(&mut self, path: &str) -> Option<LeafHandle> {
+    pub fn get_leaf_handle(&mut self, path: &str) -> Option<LeafHandle<'_>> {
         match self.get_handle(path)? {
             NodeHandle::Leaf(leaf) => Some(leaf),
             _ => None,
         }
     }
 
-    pub fn get_group_handle(&mut self, path: &str) -> Option<GroupHandle> {
+    pub fn get_group_handle(&mut self, path: &str) -> Option<GroupHandle<'_>> {
         match self.get_handle(path)? {
             NodeHandle::Group(group) => Some(group),
             _ => None,
@@ -81,7 +81,7 @@ impl<'a> MetaHandle<'a> {
         }
     }
 
-    pub fn add_leaf(&mut self, name: &str, deferred: bool) -> Result<LeafHandle, AddNodeError> {
+    pub fn add_leaf(&mut self, name: &str, deferred: bool) -> Result<LeafHandle<'_>, AddNodeError> {
         if let Some(group) = self.check_common() {
             let mut group_handle = GroupHandle { id: group, template: self.template };
             let leaf = group_handle.add_leaf(name, deferred)?;
@@ -92,7 +92,7 @@ impl<'a> MetaHandle<'a> {
         }
     }
     
-    pub fn add_group(&mut self, name: &str) -> Result<GroupHandle, AddNodeError> {
+    pub fn add_group(&mut self, name: &str) -> Result<GroupHandle<'_>, AddNodeError> {
         if let Some(group) = self.check_common() {
             let mut group_handle = GroupHandle { id: group, template: self.template };
             let group = group_handle.add_group(name)?;
@@ -104,6 +104,7 @@ impl<'a> MetaHandle<'a> {
     }
 
     pub fn set_value(&mut self, value: Metadata) -> Result<(), EditMetaError> {
+        let outermost = self.template.begin_edit();
         match (&mut self.template.get_mut_meta_by_id(self.id).unwrap().data, value) {
             (Metadata::Common { inner: _, value: ref mut old_value }, Metadata::Common { inner: _, value }) => {
                 *old_value = value;
@@ -117,15 +118,77 @@ impl<'a> MetaHandle<'a> {
             (Metadata::Constraint(ref mut old), Metadata::Constraint(new)) => {
                 *old = new;
             }
+            (Metadata::Modifier(ref mut old), Metadata::Modifier(new)) => {
+                *old = new;
+            }
+            (Metadata::Ident(ref mut old), Metadata::Ident(new)) => {
+                *old = new;
+            }
+            (Metadata::Table(ref mut old), Metadata::Table(new)) => {
+                *old = new;
+            }
+            (Metadata::Enum(ref mut old), Metadata::Enum(new)) => {
+                *old = new;
+            }
+            (Metadata::Default(ref mut old), Metadata::Default(new)) => {
+                *old = new;
+            }
+            (Metadata::Collection(ref mut old), Metadata::Collection(new)) => {
+                *old = new;
+            }
             _ => return Err(EditMetaError::WrongKind),
         }
 
+        self.template.refresh_dependencies(self.id);
+        self.template.invalidate_dependents(self.id);
+        self.template.record_edit(outermost);
+
         Ok(())
     }
 
+    /// Adds a named contribution to a `Sum`, replacing any existing contribution from the same source
+    pub fn push_contribution(&mut self, source: &str, expr: Expr) -> Result<(), EditMetaError> {
+        let outermost = self.template.begin_edit();
+        let Some(Meta { data: Metadata::Sum(contributions), .. }) = self.template.get_mut_meta_by_id(self.id) else {
+            return Err(EditMetaError::WrongKind);
+        };
+
+        let contribution = Contribution { source: source.to_owned(), expr };
+
+        match contributions.iter_mut().find(|old| old.source == source) {
+            Some(old) => *old = contribution,
+            None => contributions.push(contribution),
+        }
+
+        self.template.refresh_dependencies(self.id);
+        self.template.invalidate_dependents(self.id);
+        self.template.record_edit(outermost);
+
+        Ok(())
+    }
+
+    /// Removes the contribution from `source` from a `Sum`, returning its expression if there was one
+    pub fn remove_contribution(&mut self, source: &str) -> Result<Option<Expr>, EditMetaError> {
+        let outermost = self.template.begin_edit();
+        let Some(Meta { data: Metadata::Sum(contributions), .. }) = self.template.get_mut_meta_by_id(self.id) else {
+            return Err(EditMetaError::WrongKind);
+        };
+
+        let Some(index) = contributions.iter().position(|old| old.source == source) else {
+            return Ok(None);
+        };
+        let removed = contributions.remove(index);
+
+        self.template.refresh_dependencies(self.id);
+        self.template.invalidate_dependents(self.id);
+        self.template.record_edit(outermost);
+
+        Ok(Some(removed.expr))
+    }
+
     pub fn push_common(&mut self) -> Result<(), PushCommonError> {
         let own_node = self.template.get_meta_by_id(self.id).ok_or(PushCommonError::CommonNotExists)?;
-        let Metadata::Common { inner: ref inner, value: ref value } = &own_node.data else {
+        let Metadata::Common { .. } = &own_node.data else {
             return Err(PushCommonError::NotCommon);
         };
 
@@ -142,7 +205,7 @@ impl<'a> MetaHandle<'a> {
         };
         let neighbors = parent.children.clone();
 
-        for neighbor in neighbors {
+        for _neighbor in neighbors {
 
         }
 
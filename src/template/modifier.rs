@@ -0,0 +1,119 @@
+use super::{Template, NodeId, Integer, Expr, Value, Leaf, Meta, Metadata, EvalCache, EvalError};
+
+/// A change to the value of a leaf from a single source, such as "+2 from ring"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Modifier {
+    /// Where the modifier comes from
+    pub source: String,
+    pub op: ModifierOp,
+    /// The operand for `op`, which must evaluate to an integer
+    pub value: Expr,
+    /// Modifiers with a lower priority are applied first
+    pub priority: Integer,
+    /// Only the modifier with the highest value is applied out of all modifiers sharing a category and operation
+    /// 
+    /// Modifiers without a category always stack
+    pub category: Option<String>,
+}
+
+/// Ways a modifier can change a value
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModifierOp {
+    Add,
+    Multiply,
+    /// Replaces the value entirely
+    Override,
+    /// Raises the value to at least the operand
+    Floor,
+    /// Lowers the value to at most the operand
+    Cap,
+}
+
+impl Modifier {
+    pub fn new(source: &str, op: ModifierOp, value: Expr) -> Self {
+        Self {
+            source: source.to_owned(),
+            op,
+            value,
+            priority: 0,
+            category: None,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: Integer) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_category(mut self, category: &str) -> Self {
+        self.category = Some(category.to_owned());
+        self
+    }
+
+    /// Applies this modifier to `value` using an already evaluated operand
+    pub fn apply(&self, value: Integer, operand: Integer) -> Result<Integer, EvalError> {
+        match self.op {
+            ModifierOp::Add => value.checked_add(operand).ok_or(EvalError::Overflow),
+            ModifierOp::Multiply => value.checked_mul(operand).ok_or(EvalError::Overflow),
+            ModifierOp::Override => Ok(operand),
+            ModifierOp::Floor => Ok(value.max(operand)),
+            ModifierOp::Cap => Ok(value.min(operand)),
+        }
+    }
+}
+
+impl Template {
+    /// Gets the modifiers attached to a leaf which will be applied, in the order they will be applied, along with
+    /// the IDs of their metanodes and their evaluated operands
+    /// 
+    /// Modifiers are sorted by priority and then by the order they were attached in
+    pub(super) fn applicable_modifiers<'a>(&'a self, leaf: &Leaf, checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> Result<Vec<(NodeId, &'a Modifier, Integer)>, EvalError> {
+        let mut modifiers = Vec::new();
+
+        for id in &leaf.metadata {
+            let Some(Meta { data: Metadata::Modifier(modifier), .. }) = self.get_meta_by_id(*id) else {
+                continue;
+            };
+
+            match self.eval_leaf_inner(*id, checked, cache)? {
+                Value::Integer(operand) => modifiers.push((*id, modifier, operand)),
+                _ => return Err(EvalError::InvalidType),
+            }
+        }
+
+        // Drop everything but the highest modifier in each category
+        let mut stacked: Vec<_> = modifiers.iter().enumerate().filter(|(index, (_, modifier, operand))| {
+            let Some(category) = &modifier.category else {
+                return true;
+            };
+
+            !modifiers.iter().enumerate().any(|(other_index, (_, other, other_operand))| {
+                other.category.as_ref() == Some(category)
+                    && other.op == modifier.op
+                    && (other_operand > operand || (other_operand == operand && other_index < *index))
+            })
+        }).map(|(_, modifier)| *modifier).collect();
+
+        stacked.sort_by_key(|(_, modifier, _)| modifier.priority);
+
+        Ok(stacked)
+    }
+
+    pub(super) fn apply_modifiers(&self, leaf: &Leaf, base: Value, checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> Result<Value, EvalError> {
+        let modifiers = self.applicable_modifiers(leaf, checked, cache)?;
+
+        if modifiers.is_empty() {
+            return Ok(base);
+        }
+
+        let Value::Integer(mut value) = base else {
+            return Err(EvalError::InvalidType);
+        };
+
+        for (_, modifier, operand) in modifiers {
+            value = modifier.apply(value, operand)?;
+        }
+
+        Ok(Value::Integer(value))
+    }
+}
@@ -0,0 +1,108 @@
+use std::collections::VecDeque;
+
+use super::{Meta, Metadata, Node, NodeId, Template};
+
+/// The order a [`Walk`] visits nodes in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WalkOrder {
+    /// Every node is followed by all of its descendants before its next sibling
+    DepthFirst,
+    /// Every node at one depth is visited before any node below it
+    BreadthFirst,
+}
+
+/// An iterator over a node and everything below it, yielding each node with its dotted path
+#[derive(Clone, Debug)]
+pub struct Walk<'a> {
+    template: &'a Template,
+    order: WalkOrder,
+    /// Whether the inner groups of `Common` metanodes are visited, rather than skipped over
+    common_inner: bool,
+    pending: VecDeque<NodeId>,
+}
+
+impl Template {
+    /// Gets the children of a node with their names, looking through the inner groups of `Common` metanodes
+    /// 
+    /// Groups list their children before their metadata and leaves list their metadata
+    pub fn children(&self, id: NodeId) -> Vec<(&str, NodeId)> {
+        self.children_with(id, false).into_iter()
+            .filter_map(|child| Some((self.nodes.get(&child)?.1.as_str(), child)))
+            .collect()
+    }
+
+    /// Gets every node above `id`, nearest first and ending with the root
+    pub fn ancestors(&self, id: NodeId) -> Vec<NodeId> {
+        let mut out = Vec::new();
+        let mut current = id;
+
+        while let Some(parent) = self.parent_of(current) {
+            out.push(parent);
+            current = parent;
+        }
+
+        out
+    }
+
+    /// Walks `from` and everything below it depth-first
+    pub fn depth_first(&self, from: NodeId) -> Walk<'_> {
+        Walk::new(self, from, WalkOrder::DepthFirst)
+    }
+
+    /// Walks `from` and everything below it breadth-first
+    pub fn breadth_first(&self, from: NodeId) -> Walk<'_> {
+        Walk::new(self, from, WalkOrder::BreadthFirst)
+    }
+
+    pub(super) fn children_with(&self, id: NodeId, common_inner: bool) -> Vec<NodeId> {
+        match self.nodes.get(&id).map(|(node, _)| node) {
+            Some(Node::Group(group)) => group.children.iter().chain(group.metadata.iter()).copied().collect(),
+            Some(Node::Leaf(leaf)) => leaf.metadata.clone(),
+            Some(Node::Meta(Meta { data: Metadata::Common { inner, .. }, .. })) if common_inner => vec![*inner],
+            Some(Node::Meta(Meta { data: Metadata::Common { inner, .. }, .. })) => self.children_with(*inner, false),
+            Some(Node::Meta(_)) | None => Vec::new(),
+        }
+    }
+}
+
+impl<'a> Walk<'a> {
+    fn new(template: &'a Template, from: NodeId, order: WalkOrder) -> Self {
+        Walk { template, order, common_inner: false, pending: VecDeque::from([from]) }
+    }
+
+    /// Sets whether the inner groups of `Common` metanodes are yielded as nodes of their own
+    /// 
+    /// Inner groups can't be reached by path, so they're given the path of their metanode followed by their name
+    pub fn with_common_inner(mut self, common_inner: bool) -> Self {
+        self.common_inner = common_inner;
+        self
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (String, &'a Node);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let template = self.template;
+
+        loop {
+            let id = self.pending.pop_front()?;
+            let Some((node, name)) = template.nodes.get(&id) else {
+                continue;
+            };
+
+            let children = template.children_with(id, self.common_inner);
+            match self.order {
+                WalkOrder::DepthFirst => children.into_iter().rev().for_each(|child| self.pending.push_front(child)),
+                WalkOrder::BreadthFirst => self.pending.extend(children),
+            }
+
+            let path = match template.common_meta_of(id) {
+                Some(meta) => format!("{}.{name}", template.path_of(meta).unwrap_or_default()),
+                None => template.path_of(id).unwrap_or_default(),
+            };
+
+            return Some((path, node));
+        }
+    }
+}
@@ -0,0 +1,375 @@
+use super::{Aggregate, AggregateKind, Expr, InfixOp, Integer, Lookup, NodeId, OpKind, Template, Value, DANGLING};
+use super::render::{precedence, right_associative};
+
+/// Why source text couldn't be read as an expression, and where
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// The byte offset in the source where the problem was found
+    pub position: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    UnexpectedEnd,
+    UnexpectedChar(char),
+    /// An integer literal is too large
+    InvalidInteger,
+    /// A path doesn't lead to any node in the template
+    UnknownPath(String),
+    /// The expression ended before the end of the source
+    TrailingInput,
+    /// A word isn't one of the words allowed here
+    UnexpectedWord(String),
+    /// A literal value is needed here, not an expression
+    NotLiteral,
+}
+
+/// Reads expressions written by [`Template::render_expr`]
+pub(super) struct Parser<'a> {
+    template: &'a Template,
+    source: &'a str,
+    pub(super) position: usize,
+    /// The node plain paths are resolved from
+    root: NodeId,
+    /// Whether plain paths which aren't found from `root` are resolved from the root of the template instead
+    fall_back: bool,
+}
+
+impl Template {
+    /// Reads an expression from source text, resolving plain paths to references
+    /// 
+    /// This accepts anything written by [`Template::render_expr`], so `parse_expr(&render_expr(expr)) == Ok(expr)`
+    /// as long as the referenced nodes haven't moved in between
+    pub fn parse_expr(&self, source: &str) -> Result<Expr, ParseError> {
+        Parser::new(self, source).whole_expr()
+    }
+
+    /// Reads an expression like [`Template::parse_expr`], resolving plain paths from `origin` first
+    /// 
+    /// Paths which aren't found below `origin` are resolved from the root, so both `mod` and `abilities.mod` work from
+    /// `abilities`
+    pub fn parse_expr_from(&self, source: &str, origin: NodeId) -> Result<Expr, ParseError> {
+        let mut parser = Parser::new(self, source).with_root(origin);
+        parser.fall_back = true;
+
+        parser.whole_expr()
+    }
+}
+
+impl<'a> Parser<'a> {
+    pub(super) fn new(template: &'a Template, source: &'a str) -> Self {
+        Parser { template, source, position: 0, root: 0, fall_back: false }
+    }
+
+    /// Parses an expression which must be the whole source
+    fn whole_expr(mut self) -> Result<Expr, ParseError> {
+        let expr = self.expr(0)?;
+
+        self.skip_whitespace();
+        match self.peek() {
+            Some(_) => Err(self.error(ParseErrorKind::TrailingInput)),
+            None => Ok(expr),
+        }
+    }
+
+    /// Resolves plain paths from `root` rather than the root of the template
+    pub(super) fn with_root(mut self, root: NodeId) -> Self {
+        self.root = root;
+
+        self
+    }
+
+    pub(super) fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError { position: self.position, kind }
+    }
+
+    pub(super) fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(c) => self.error(ParseErrorKind::UnexpectedChar(c)),
+            None => self.error(ParseErrorKind::UnexpectedEnd),
+        }
+    }
+
+    pub(super) fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    pub(super) fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+
+        Some(c)
+    }
+
+    pub(super) fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    /// Skips whitespace and consumes `token` if it's next
+    pub(super) fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+
+        if self.source[self.position..].starts_with(token) {
+            self.position += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub(super) fn expect(&mut self, token: &str) -> Result<(), ParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Parses operations that bind at least as tightly as `min_precedence`
+    pub(super) fn expr(&mut self, min_precedence: u8) -> Result<Expr, ParseError> {
+        let mut lhs = self.primary()?;
+
+        while let Some(kind) = self.peek_operator() {
+            let precedence = precedence(kind);
+            if precedence < min_precedence {
+                break;
+            }
+
+            self.eat(kind.symbol());
+            let next = if right_associative(kind) { precedence } else { precedence + 1 };
+            let rhs = self.expr(next)?;
+
+            lhs = Expr::InfixOp(Box::new(InfixOp { lhs, rhs, kind }));
+        }
+
+        Ok(lhs)
+    }
+
+    pub(super) fn peek_operator(&mut self) -> Option<OpKind> {
+        self.skip_whitespace();
+
+        [OpKind::Coalesce, OpKind::Eq, OpKind::Ne, OpKind::Add, OpKind::Sub, OpKind::Mul, OpKind::Div, OpKind::Pow]
+            .into_iter()
+            .find(|kind| self.source[self.position..].starts_with(kind.symbol()))
+    }
+
+    pub(super) fn primary(&mut self) -> Result<Expr, ParseError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            Some('(') => {
+                self.bump();
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+
+                Ok(expr)
+            },
+            Some('[') => {
+                self.bump();
+                let mut values = Vec::new();
+
+                if !self.eat("]") {
+                    loop {
+                        values.push(self.expr(0)?);
+
+                        if self.eat("]") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+
+                Ok(Expr::Literal(Value::List(values)))
+            },
+            Some('"') => self.string().map(|string| Expr::Literal(Value::String(string))),
+            Some('-') if !self.source[self.position + 1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                self.bump();
+                let lhs = self.primary()?;
+
+                Ok(Expr::InfixOp(Box::new(InfixOp { lhs, rhs: 0.into(), kind: OpKind::Neg })))
+            },
+            Some(c) if c == '-' || c.is_ascii_digit() => self.integer().map(|value| Expr::Literal(Value::Integer(value))),
+            Some('$') => {
+                self.bump();
+
+                self.path().map(Expr::PathRef)
+            },
+            Some('@') => {
+                self.bump();
+
+                self.node().map(Expr::IdentRef)
+            },
+            Some(_) => {
+                if let Some(expr) = self.function()? {
+                    return Ok(expr);
+                }
+
+                let id = self.node()?;
+
+                if self.source[self.position..].starts_with("::") {
+                    self.position += 2;
+                    let variant = self.segment()?;
+
+                    Ok(Expr::Literal(Value::Enum { id, variant }))
+                } else {
+                    Ok(Expr::Reference(id))
+                }
+            },
+            None => Err(self.unexpected()),
+        }
+    }
+
+    /// Parses a call like `has(x)` if one is next, leaving the position alone otherwise
+    pub(super) fn function(&mut self) -> Result<Option<Expr>, ParseError> {
+        let start = self.position;
+        let rest = &self.source[start..];
+        let name_len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        let name = &rest[..name_len];
+
+        self.position += name_len;
+        if !matches!(name, "sum" | "count" | "has" | "lookup") || !self.eat("(") {
+            self.position = start;
+            return Ok(None);
+        }
+
+        let expr = match name {
+            "sum" | "count" => {
+                self.skip_whitespace();
+                let pattern = self.path()?;
+                let kind = if name == "sum" { AggregateKind::Sum } else { AggregateKind::Count };
+
+                Expr::Aggregate(Aggregate { kind, pattern })
+            },
+            "has" => Expr::Has(Box::new(self.expr(0)?)),
+            _ => {
+                self.skip_whitespace();
+                let table = self.path()?;
+                self.expect(",")?;
+                let key = self.expr(0)?;
+
+                Expr::Lookup(Box::new(Lookup { table, key }))
+            },
+        };
+
+        self.expect(")")?;
+
+        Ok(Some(expr))
+    }
+
+    /// Parses a node written as an absolute path or as `#id`
+    /// 
+    /// `#id` is only written for nodes that no longer exist, and the ID may belong to another node by the time it's
+    /// read, so it's read as [`DANGLING`] which never refers to anything
+    pub(super) fn node(&mut self) -> Result<NodeId, ParseError> {
+        if self.peek() == Some('#') {
+            self.bump();
+            let start = self.position;
+
+            while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                self.bump();
+            }
+
+            return match self.source[start..self.position].parse::<NodeId>() {
+                Ok(_) => Ok(DANGLING),
+                Err(_) if start == self.position => Err(self.unexpected()),
+                Err(_) => Err(self.error(ParseErrorKind::InvalidInteger)),
+            };
+        }
+
+        let start = self.position;
+        let path = self.path()?;
+
+        self.template.get_node_from(&path, self.root)
+            .or_else(|| self.template.get_node_from(&path, 0).filter(|_| self.fall_back))
+            .ok_or(ParseError { position: start, kind: ParseErrorKind::UnknownPath(path) })
+    }
+
+    /// Parses a dotted path, removing the quotes from any quoted segments
+    pub(super) fn path(&mut self) -> Result<String, ParseError> {
+        let mut segments = vec![self.segment()?];
+
+        while self.peek() == Some('.') {
+            self.bump();
+            segments.push(self.segment()?);
+        }
+
+        Ok(segments.join("."))
+    }
+
+    pub(super) fn segment(&mut self) -> Result<String, ParseError> {
+        let start = self.position;
+
+        match self.peek() {
+            Some('`') => {
+                self.bump();
+                let mut segment = String::new();
+
+                loop {
+                    match self.bump() {
+                        Some('`') => return Ok(segment),
+                        Some('\\') => segment.push(self.bump().ok_or_else(|| self.unexpected())?),
+                        Some(c) => segment.push(c),
+                        None => return Err(self.unexpected()),
+                    }
+                }
+            },
+            Some('^') => while self.peek() == Some('^') {
+                self.bump();
+            },
+            Some('*') => while self.peek() == Some('*') {
+                self.bump();
+            },
+            Some(c) if c.is_alphabetic() || c == '_' => while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                self.bump();
+            },
+            _ => return Err(self.unexpected()),
+        }
+
+        Ok(self.source[start..self.position].to_owned())
+    }
+
+    pub(super) fn string(&mut self) -> Result<String, ParseError> {
+        if self.peek() != Some('"') {
+            return Err(self.unexpected());
+        }
+
+        self.bump();
+        let mut string = String::new();
+
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.bump() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some(c) => string.push(c),
+                    None => return Err(self.unexpected()),
+                },
+                Some(c) => string.push(c),
+                None => return Err(self.unexpected()),
+            }
+        }
+    }
+
+    pub(super) fn integer(&mut self) -> Result<Integer, ParseError> {
+        let start = self.position;
+
+        if self.peek() == Some('-') {
+            self.bump();
+        }
+
+        if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            return Err(self.unexpected());
+        }
+
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.bump();
+        }
+
+        self.source[start..self.position].parse()
+            .map_err(|_| ParseError { position: start, kind: ParseErrorKind::InvalidInteger })
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+
+use super::{EvalCache, Leaf, Meta, Metadata, ModifierOp, Node, NodeId, Template, TableKey};
+
+/// Renders a template as an indented tree, created by [`Template::tree`]
+#[derive(Clone, Copy, Debug)]
+pub struct TreeView<'a> {
+    template: &'a Template,
+    root: NodeId,
+    /// Whether leaves are evaluated and shown with their current values
+    values: bool,
+}
+
+impl Template {
+    /// Renders the whole template as an indented tree, one node per line
+    /// 
+    /// ```text
+    /// abilities (group)
+    ///   strength (leaf) = 16
+    ///   mod (common)
+    ///     value (leaf) = (abilities.strength - 10) / 2
+    /// ```
+    pub fn tree(&self) -> TreeView<'_> {
+        self.tree_from(0)
+    }
+
+    /// Renders the nodes below `root` as an indented tree
+    pub fn tree_from(&self, root: NodeId) -> TreeView<'_> {
+        TreeView { template: self, root, values: false }
+    }
+}
+
+impl<'a> TreeView<'a> {
+    /// Sets whether leaves are evaluated and shown with their current values, like `mod (leaf) = score / 2 => 3`
+    pub fn with_values(mut self, values: bool) -> Self {
+        self.values = values;
+        self
+    }
+
+    fn fmt_node(&self, f: &mut fmt::Formatter<'_>, id: NodeId, depth: usize, cache: &mut EvalCache) -> fmt::Result {
+        let template = self.template;
+        let Some((node, name)) = template.nodes.get(&id) else {
+            return Ok(());
+        };
+
+        write!(f, "{:indent$}{name} ", "", indent = depth * 2)?;
+
+        match node {
+            Node::Group(_) => write!(f, "(group)")?,
+            Node::Leaf(leaf) => self.fmt_leaf(f, leaf)?,
+            Node::Meta(meta) => self.fmt_meta(f, meta)?,
+        }
+
+        // Groups and metanodes that only describe other nodes don't have values of their own
+        let has_value = match node {
+            Node::Leaf(_) => true,
+            Node::Group(_) => false,
+            Node::Meta(meta) => matches!(
+                meta.data,
+                Metadata::Sum(_) | Metadata::Ident(_) | Metadata::Concat(_) | Metadata::Modifier(_) | Metadata::Default(_),
+            ),
+        };
+
+        if self.values && has_value {
+            match template.eval_leaf_cached(id, cache) {
+                Ok(value) => write!(f, " => {value}")?,
+                Err(err) => write!(f, " => error: {}", template.report_eval(err, cache.failure_chain().unwrap_or(&[id])))?,
+            }
+        }
+
+        writeln!(f)?;
+
+        for child in template.children_with(id, false) {
+            self.fmt_node(f, child, depth + 1, cache)?;
+        }
+
+        Ok(())
+    }
+
+    fn fmt_leaf(&self, f: &mut fmt::Formatter<'_>, leaf: &Leaf) -> fmt::Result {
+        let template = self.template;
+
+        match (leaf.optional, leaf.deferred) {
+            (true, _) => write!(f, "(leaf, optional)")?,
+            (false, true) => write!(f, "(leaf, deferred)")?,
+            (false, false) => write!(f, "(leaf)")?,
+        }
+
+        if let Some(expr) = &leaf.value {
+            write!(f, " = {}", template.render_expr(expr))?;
+        }
+
+        Ok(())
+    }
+
+    fn fmt_meta(&self, f: &mut fmt::Formatter<'_>, meta: &Meta) -> fmt::Result {
+        let template = self.template;
+
+        match &meta.data {
+            Metadata::Common { .. } => write!(f, "(common)"),
+            Metadata::CommonProxy { .. } => write!(f, "(common proxy)"),
+            Metadata::Sum(contributions) => {
+                let contributions: Vec<_> = contributions.iter()
+                    .map(|contribution| format!("{}: {}", contribution.source, template.render_expr(&contribution.expr)))
+                    .collect();
+
+                write!(f, "(sum) = {}", contributions.join(", "))
+            },
+            Metadata::Ident(mode) => write!(f, "(ident, {mode:?})"),
+            Metadata::Concat(elements) => {
+                let elements: Vec<_> = elements.iter().map(|element| template.render_expr(&element.expr)).collect();
+
+                write!(f, "(concat) = {}", elements.join(" ~ "))
+            },
+            Metadata::Constraint(constraint) => write!(f, "(constraint) {constraint:?}"),
+            Metadata::Modifier(modifier) => {
+                let op = match modifier.op {
+                    ModifierOp::Add => "+",
+                    ModifierOp::Multiply => "*",
+                    ModifierOp::Override => "=",
+                    ModifierOp::Floor => "at least",
+                    ModifierOp::Cap => "at most",
+                };
+
+                write!(f, "(modifier from {}) {op} {}", modifier.source, template.render_expr(&modifier.value))
+            },
+            Metadata::Table(table) => {
+                let rows: Vec<_> = table.rows.iter().map(|(key, value)| match key {
+                    TableKey::Exact(key) => format!("{key}: {value}"),
+                    TableKey::Range(start, end) => format!("{start}..={end}: {value}"),
+                }).collect();
+
+                write!(f, "(table) {}", rows.join(", "))
+            },
+            Metadata::Enum(variants) => write!(f, "(enum) {}", variants.join(" | ")),
+            Metadata::Default(expr) => write!(f, "(default) = {}", template.render_expr(expr)),
+            Metadata::Collection(collection) => {
+                let fields: Vec<_> = collection.schema.children(0).into_iter().map(|(name, _)| name).collect();
+
+                match collection.keyed {
+                    true => write!(f, "(keyed collection) of {}", fields.join(", ")),
+                    false => write!(f, "(collection) of {}", fields.join(", ")),
+                }
+            },
+        }
+    }
+}
+
+impl<'a> fmt::Display for TreeView<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut cache = EvalCache::new();
+
+        for child in self.template.children_with(self.root, false) {
+            self.fmt_node(f, child, 0, &mut cache)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use super::{Template, NodeId, Integer, Node, Meta, Metadata, Value, Aggregate, AggregateKind, EvalCache, EvalError};
+
+impl Template {
+    /// Finds every node matching a dotted pattern, along with its path
+    /// 
+    /// `*` matches any single child and `**` matches any number of levels of children, so `abilities.*.mod` finds the
+    /// `mod` of each ability and `skills.**` finds everything inside `skills`. Wildcards don't match metadata, but
+    /// metadata can still be named directly
+    pub fn query(&self, pattern: &str) -> Vec<(NodeId, String)> {
+        self.query_from(pattern, 0).into_iter()
+            .filter_map(|id| Some((id, self.path_of(id)?)))
+            .collect()
+    }
+
+    /// Finds every node matching a pattern, which may be relative to `origin`
+    pub(super) fn query_from(&self, pattern: &str, origin: NodeId) -> Vec<NodeId> {
+        let mut out = Vec::new();
+
+        if let Some((base, rest)) = self.split_relative(pattern, origin) {
+            let segments: Vec<&str> = rest.map(|rest| rest.split('.').collect()).unwrap_or_default();
+            self.match_segments(base, &segments, &mut out);
+        }
+
+        out
+    }
+
+    fn match_segments(&self, id: NodeId, segments: &[&str], out: &mut Vec<NodeId>) {
+        let Some((first, rest)) = segments.split_first() else {
+            if !out.contains(&id) {
+                out.push(id);
+            }
+
+            return;
+        };
+
+        match *first {
+            "*" => {
+                for child in self.wildcard_children(id) {
+                    self.match_segments(child, rest, out);
+                }
+            },
+            "**" => {
+                // A trailing `**` only matches things inside the node, not the node itself
+                if !rest.is_empty() {
+                    self.match_segments(id, rest, out);
+                }
+
+                for child in self.wildcard_children(id) {
+                    if rest.is_empty() {
+                        self.match_segments(child, rest, out);
+                    }
+
+                    self.match_segments(child, segments, out);
+                }
+            },
+            name => {
+                if let Some(child) = self.get_node_from(name, id) {
+                    self.match_segments(child, rest, out);
+                }
+            },
+        }
+    }
+
+    /// Gets the children of a node that can be matched by a wildcard
+    fn wildcard_children(&self, id: NodeId) -> Vec<NodeId> {
+        match self.nodes.get(&id).map(|(node, _)| node) {
+            Some(Node::Group(group)) => group.children.clone(),
+            Some(Node::Meta(Meta { data: Metadata::Common { inner, .. }, .. })) => self.wildcard_children(*inner),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Gets the nodes an aggregate will combine, skipping any groups matched by its pattern
+    pub(super) fn aggregate_matches(&self, aggregate: &Aggregate, checked: &[NodeId]) -> Vec<NodeId> {
+        let origin = checked.last().copied().unwrap_or(0);
+
+        self.query_from(&aggregate.pattern, origin).into_iter()
+            .filter(|id| !matches!(self.nodes.get(id), Some((Node::Group(_), _))))
+            .collect()
+    }
+
+    pub(super) fn eval_aggregate(&self, aggregate: &Aggregate, checked: &mut Vec<NodeId>, cache: &mut EvalCache) -> Result<Value, EvalError> {
+        let matches = self.aggregate_matches(aggregate, checked);
+
+        match aggregate.kind {
+            AggregateKind::Sum => {
+                let mut sum = 0;
+
+                for id in matches {
+                    match self.eval_leaf_inner(id, checked, cache)? {
+                        Value::Integer(value) => sum = Integer::checked_add(sum, value).ok_or(EvalError::Overflow)?,
+                        _ => return Err(EvalError::InvalidType),
+                    }
+                }
+
+                Ok(Value::Integer(sum))
+            },
+            AggregateKind::Count => Ok(Value::Integer(matches.len() as _)),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt;
+
+use super::{AggregateKind, Expr, NodeId, OpKind, Template, Value};
+
+/// How tightly an operation binds, higher is tighter
+pub(super) fn precedence(kind: OpKind) -> u8 {
+    match kind {
+        OpKind::Coalesce => 1,
+        OpKind::Eq | OpKind::Ne => 2,
+        OpKind::Add | OpKind::Sub => 3,
+        OpKind::Mul | OpKind::Div => 4,
+        OpKind::Pow => 5,
+        // Written as a prefix, so it never needs parentheses
+        OpKind::Neg => u8::MAX,
+    }
+}
+
+/// Whether a chain of the operation groups from the right, like `2 ^ 3 ^ 2`
+pub(super) fn right_associative(kind: OpKind) -> bool {
+    kind == OpKind::Pow
+}
+
+/// Whether a path segment can be written without backticks
+pub(super) fn is_plain_segment(segment: &str) -> bool {
+    let mut chars = segment.chars();
+
+    match chars.next() {
+        Some('^') => chars.all(|c| c == '^'),
+        Some('*') => segment == "*" || segment == "**",
+        Some(c) if c.is_alphabetic() || c == '_' => chars.all(|c| c.is_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+/// Writes a single name, quoting it with backticks if it couldn't be read back on its own
+pub(super) fn render_segment(segment: &str) -> String {
+    if is_plain_segment(segment) {
+        segment.to_owned()
+    } else {
+        format!("`{}`", segment.replace('\\', "\\\\").replace('`', "\\`"))
+    }
+}
+
+/// Writes a path, quoting any segments that couldn't be read back on their own
+pub(super) fn render_path(path: &str) -> String {
+    let segments: Vec<_> = path.split('.').map(render_segment).collect();
+
+    segments.join(".")
+}
+
+pub(super) fn render_string(string: &str) -> String {
+    let mut out = String::from("\"");
+
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+impl OpKind {
+    /// The symbol the operation is written with
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            OpKind::Add => "+",
+            OpKind::Sub => "-",
+            OpKind::Mul => "*",
+            OpKind::Div => "/",
+            OpKind::Pow => "^",
+            OpKind::Neg => "-",
+            OpKind::Eq => "==",
+            OpKind::Ne => "!=",
+            OpKind::Coalesce => "??",
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Integer(value) => write!(f, "{value}"),
+            Value::String(value) => write!(f, "{value:?}"),
+            Value::List(values) => write!(f, "[{} items]", values.len()),
+            Value::Enum { variant, .. } => write!(f, "{variant}"),
+        }
+    }
+}
+
+impl Template {
+    /// Writes an expression as source text which [`Template::parse_expr`] can read back
+    /// 
+    /// - References are written as dotted paths like `abilities.strength`, or `#id` if the node can't be found, which
+    ///   is read back as a reference that never resolves
+    /// - Path references are marked with `$`, like `$^.strength`, since they're looked up again on every evaluation
+    /// - Ident references are marked with `@`, like `@abilities.mod.source`
+    /// - Enum values are written as the path of their `Enum` metanode and the variant, like `size::large`
+    /// - Infix operations only get the parentheses they need
+    pub fn render_expr(&self, expr: &Expr) -> String {
+        match expr {
+            Expr::Literal(Value::Integer(value)) => value.to_string(),
+            Expr::Literal(Value::String(value)) => render_string(value),
+            Expr::Literal(Value::List(values)) => {
+                let values: Vec<_> = values.iter().map(|value| self.render_expr(value)).collect();
+
+                format!("[{}]", values.join(", "))
+            },
+            Expr::Literal(Value::Enum { id, variant }) => format!("{}::{}", self.render_node(*id), render_segment(variant)),
+            Expr::Reference(id) => self.render_node(*id),
+            Expr::PathRef(path) => format!("${}", render_path(path)),
+            Expr::IdentRef(id) => format!("@{}", self.render_node(*id)),
+            Expr::Aggregate(aggregate) => match aggregate.kind {
+                AggregateKind::Sum => format!("sum({})", render_path(&aggregate.pattern)),
+                AggregateKind::Count => format!("count({})", render_path(&aggregate.pattern)),
+            },
+            Expr::Has(inner) => format!("has({})", self.render_expr(inner)),
+            Expr::Lookup(lookup) => format!("lookup({}, {})", render_path(&lookup.table), self.render_expr(&lookup.key)),
+            Expr::InfixOp(op) if op.kind == OpKind::Neg => match &op.lhs {
+                // A minus sign before a digit would make a negative literal instead
+                Expr::Literal(Value::Integer(_)) => format!("-({})", self.render_expr(&op.lhs)),
+                Expr::InfixOp(inner) if inner.kind != OpKind::Neg => format!("-({})", self.render_expr(&op.lhs)),
+                lhs => format!("-{}", self.render_expr(lhs)),
+            },
+            Expr::InfixOp(op) => {
+                let outer = precedence(op.kind);
+                let side = |expr: &Expr, is_lhs: bool| match expr {
+                    Expr::InfixOp(inner) if precedence(inner.kind) < outer
+                        || (precedence(inner.kind) == outer && is_lhs == right_associative(op.kind)) => {
+                        format!("({})", self.render_expr(expr))
+                    },
+                    _ => self.render_expr(expr),
+                };
+
+                format!("{} {} {}", side(&op.lhs, true), op.kind.symbol(), side(&op.rhs, false))
+            },
+        }
+    }
+
+    /// Writes a reference to a node by its path, falling back to its ID
+    pub(super) fn render_node(&self, id: NodeId) -> String {
+        match self.path_of(id) {
+            Some(path) if !path.is_empty() => render_path(&path),
+            _ => format!("#{id}"),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use super::{Template, NodeId, Integer, Value, ValueKind, Meta, Metadata, EvalError};
+
+/// Rows of keys and values, such as proficiency bonus by level
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Table {
+    /// Rows are checked in order, so the first row containing a key is used
+    pub rows: Vec<(TableKey, Value)>,
+}
+
+/// The keys covered by a row in a table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TableKey {
+    /// Matches a single value
+    Exact(Value),
+    /// Matches any integer from `start` to `end`, inclusive
+    Range(Integer, Integer),
+}
+
+impl TableKey {
+    pub fn contains(&self, key: &Value) -> bool {
+        match (self, key) {
+            (TableKey::Exact(exact), key) => exact == key,
+            (TableKey::Range(start, end), Value::Integer(key)) => (start..=end).contains(&key),
+            _ => false,
+        }
+    }
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a row matching a single key
+    pub fn with_row(mut self, key: Value, value: Value) -> Self {
+        self.rows.push((TableKey::Exact(key), value));
+        self
+    }
+
+    /// Adds a row matching every integer from `start` to `end`, inclusive
+    pub fn with_range(mut self, start: Integer, end: Integer, value: Value) -> Self {
+        self.rows.push((TableKey::Range(start, end), value));
+        self
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        self.rows.iter().find(|(row, _)| row.contains(key)).map(|(_, value)| value)
+    }
+
+    /// The type of values in the table, based on its first row
+    pub fn value_kind(&self) -> ValueKind {
+        self.rows.first().map(|(_, value)| value.into()).unwrap_or(ValueKind::Undefined)
+    }
+}
+
+impl Template {
+    /// Looks up `key` in the table at `path`, returning the ID of the table along with the value
+    pub(super) fn eval_lookup(&self, path: &str, key: Value, checked: &[NodeId]) -> Result<(NodeId, Value), EvalError> {
+        let id = self.resolve_path_ref(path, checked)?;
+
+        let Some(Meta { data: Metadata::Table(table), .. }) = self.get_meta_by_id(id) else {
+            return Err(EvalError::NotATable(id));
+        };
+
+        match table.get(&key) {
+            Some(value) => Ok((id, value.clone())),
+            None => Err(EvalError::KeyNotInTable { id, key }),
+        }
+    }
+}
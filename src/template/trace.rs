@@ -0,0 +1,40 @@
+use std::{fmt, sync::Arc};
+
+use super::{Template, NodeId};
+
+/// A function called with every [`TraceEvent`] while evaluating
+/// 
+/// Templates can be evaluated from several threads at once, so the hook has to be shareable between them
+pub type TraceHook = Arc<dyn Fn(TraceEvent) + Send + Sync>;
+
+/// The hook stored in a template, which can't be printed like the rest of it
+#[derive(Clone)]
+pub(super) struct Tracer(pub(super) TraceHook);
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Tracer")
+    }
+}
+
+/// Something that happened during evaluation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceEvent<'a> {
+    /// A node is being evaluated because there was no cached value for it
+    Eval { id: NodeId },
+    /// An `Ident` metanode looked at `node` while searching for the node it identifies
+    IdentStep { meta: NodeId, node: NodeId, name: &'a str },
+}
+
+impl Template {
+    /// Sets the function to call with details about evaluation, or removes it with `None`
+    pub fn set_trace(&mut self, hook: Option<TraceHook>) {
+        self.trace = hook.map(Tracer);
+    }
+
+    pub(super) fn emit(&self, event: TraceEvent) {
+        if let Some(Tracer(hook)) = &self.trace {
+            hook(event);
+        }
+    }
+}
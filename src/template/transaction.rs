@@ -0,0 +1,65 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use super::{ErrorReport, EvalCache, EvalError, Template};
+
+/// Why a transaction was rolled back
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransactionError<E> {
+    /// One of the edits failed
+    Edit(E),
+    /// The edits left the template with a problem it didn't have before, like a cycle or a broken constraint
+    Invalid(ErrorReport<EvalError>),
+}
+
+impl Template {
+    /// Makes several edits together, so either all of them are kept or none are
+    /// 
+    /// If `edits` returns an error, or the template has a problem afterwards that [`Template::check`] didn't find
+    /// before, the template is put back how it was and the first error is returned. It's also put back if `edits`
+    /// panics, before the panic carries on. Caches are only invalidated once all the edits are made, and every value
+    /// evaluated while checking is kept. The whole transaction is undone with a single [`Template::undo`]
+    /// 
+    /// ```
+    /// # use peanut::{NodeTree, Template};
+    /// let mut template = Template::load("leaf level = 1\nleaf hit_points = level * 8\n").unwrap();
+    /// let hit_points = template.parse_expr("level * 10").unwrap();
+    ///
+    /// template.transaction(|tx| {
+    ///     tx.get_leaf_handle("level").unwrap().set_value(2.into())?;
+    ///     tx.get_leaf_handle("hit_points").unwrap().set_expr(hit_points)?;
+    ///
+    ///     Ok::<_, peanut::EditLeafError>(())
+    /// }).unwrap();
+    /// ```
+    pub fn transaction<T, E>(&mut self, edits: impl FnOnce(&mut Template) -> Result<T, E>) -> Result<T, TransactionError<E>> {
+        // Values found now stay cached unless the edits invalidate them, so the second check only evaluates what changed
+        let mut cache = EvalCache::new();
+        let existing = self.check_cached(&mut cache);
+        self.store_cache(cache);
+
+        self.single_edit(|template| {
+            // Transactions inside transactions still check their own edits, so they need up to date caches too
+            let outermost = template.pending_invalidation.is_none();
+            let pending = template.pending_invalidation.replace(Vec::new()).unwrap_or_default();
+
+            // Invalidation can't be left paused if an edit panics
+            let result = panic::catch_unwind(AssertUnwindSafe(|| edits(template)));
+
+            let invalidated = template.pending_invalidation.take().unwrap_or_default();
+            template.invalidate_all_dependents(pending.into_iter().chain(invalidated).collect());
+            if !outermost {
+                template.pending_invalidation = Some(Vec::new());
+            }
+
+            let out = result.unwrap_or_else(|payload| panic::resume_unwind(payload)).map_err(TransactionError::Edit)?;
+
+            let mut cache = EvalCache::new();
+            if let Some(report) = template.check_cached(&mut cache).into_iter().find(|report| !existing.contains(report)) {
+                return Err(TransactionError::Invalid(report));
+            }
+            template.store_cache(cache);
+
+            Ok(out)
+        })
+    }
+}
@@ -1,10 +1,10 @@
-use super::{Template, GroupHandle, NodeHandle, LeafHandle, Node, Leaf, Group, AddNodeError, MetaHandle, Handle, NodeId};
+use super::{Template, GroupHandle, NodeHandle, LeafHandle, Node, Leaf, Group, AddNodeError, MetaHandle, Handle};
 
 impl NodeTree for Template {}
 impl<'a> NodeTree for GroupHandle<'a> {}
 
 pub trait NodeTree: Handle {
-    fn get_handle(&mut self, path: &str) -> Option<NodeHandle> {
+    fn get_handle(&mut self, path: &str) -> Option<NodeHandle<'_>> {
         let id = self.get_id();
         let template = self.get_template_mut();
         let node = template.nodes.get(&template.get_node_from(path, id)?).map(|(node, _)| node)?;
@@ -16,14 +16,14 @@ pub trait NodeTree: Handle {
         })
     }
 
-    fn get_leaf_handle(&mut self, path: &str) -> Option<LeafHandle> {
+    fn get_leaf_handle(&mut self, path: &str) -> Option<LeafHandle<'_>> {
         match self.get_handle(path)? {
             NodeHandle::Leaf(leaf) => Some(leaf),
             _ => None,
         }
     }
 
-    fn get_group_handle(&mut self, path: &str) -> Option<GroupHandle> {
+    fn get_group_handle(&mut self, path: &str) -> Option<GroupHandle<'_>> {
         match self.get_handle(path)? {
             NodeHandle::Group(group) => Some(group),
             _ => None,
@@ -50,13 +50,13 @@ pub trait NodeTree: Handle {
         }
     }
 
-    fn add_leaf(&mut self, name: &str, deferred: bool) -> Result<LeafHandle, AddNodeError> {
+    fn add_leaf(&mut self, name: &str, deferred: bool) -> Result<LeafHandle<'_>, AddNodeError> {
         let id = self.get_id();
         let template = self.get_template_mut();
         template.add_leaf_to(name, id, deferred)
     }
     
-    fn add_group(&mut self, name: &str) -> Result<GroupHandle, AddNodeError> {
+    fn add_group(&mut self, name: &str) -> Result<GroupHandle<'_>, AddNodeError> {
         let id = self.get_id();
         let template = self.get_template_mut();
         template.add_group_to(name, id)